@@ -0,0 +1,213 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::lexi_data::LexiData;
+
+/// Counters for the client side cache, as reported by `Client::cache_stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// `get`s answered from the cache.
+    pub hits: u64,
+    /// `get`s that had to ask the server.
+    pub misses: u64,
+    /// Entries dropped because the server said their key changed.
+    pub invalidations: u64,
+    /// Entries dropped to make room for newer ones.
+    pub evictions: u64,
+}
+
+impl CacheStats {
+    /// The share of `get`s answered from the cache, or 0 before any `get`.
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+/// a least recently used map from key to the reply of `GET`
+pub(crate) struct Cache {
+    capacity: usize,
+    /// value and last use of every key
+    entries: HashMap<String, (LexiData, u64)>,
+    /// keys by last use, oldest first
+    recency: BTreeMap<u64, String>,
+    tick: u64,
+    stats: CacheStats,
+}
+
+impl Cache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// looks `key` up, counting a hit or a miss
+    pub(crate) fn get(&mut self, key: &str) -> Option<LexiData> {
+        let tick = self.next_tick();
+        match self.entries.get_mut(key) {
+            Some((data, used)) => {
+                self.stats.hits += 1;
+                let key = self.recency.remove(used).expect("cached key has a use");
+                self.recency.insert(tick, key);
+                *used = tick;
+                Some(data.clone())
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub(crate) fn insert(&mut self, key: String, data: LexiData) {
+        if self.capacity == 0 {
+            return;
+        }
+        let tick = self.next_tick();
+        if let Some((_, used)) = self.entries.insert(key.clone(), (data, tick)) {
+            self.recency.remove(&used);
+        } else if self.entries.len() > self.capacity {
+            if let Some((_, oldest)) = self.recency.pop_first() {
+                self.entries.remove(&oldest);
+                self.stats.evictions += 1;
+            }
+        }
+        self.recency.insert(tick, key);
+    }
+
+    pub(crate) fn invalidate(&mut self, key: &str) {
+        if let Some((_, used)) = self.entries.remove(key) {
+            self.recency.remove(&used);
+            self.stats.invalidations += 1;
+        }
+    }
+
+    /// drops every entry, for when invalidations may have been missed
+    pub(crate) fn clear(&mut self) {
+        self.stats.invalidations += self.entries.len() as u64;
+        self.entries.clear();
+        self.recency.clear();
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+/// The keys a push frame says have changed, or `None` for every key.
+///
+/// Understands tracking invalidations (`invalidate [key...]`, or
+/// `invalidate` with no keys after a flush) and keyspace notifications
+/// (`message __keyspace@0__:key event` and
+/// `message __keyevent@0__:event key`, or their `pmessage` forms).
+pub(crate) fn invalidated_keys(push: &[LexiData]) -> Option<Vec<String>> {
+    let kind = push.first().and_then(LexiData::as_str).unwrap_or_default();
+    let rest = match kind.to_ascii_lowercase().as_str() {
+        "invalidate" => {
+            return match push.get(1) {
+                Some(LexiData::Array(keys)) => Some(
+                    keys.iter()
+                        .filter_map(|key| key.as_str().map(str::to_owned))
+                        .collect(),
+                ),
+                _ => None,
+            };
+        }
+        "message" => &push[1..],
+        "pmessage" => push.get(2..).unwrap_or_default(),
+        _ => return Some(Vec::new()),
+    };
+    let (Some(channel), Some(payload)) = (
+        rest.first().and_then(LexiData::as_str),
+        rest.get(1).and_then(LexiData::as_str),
+    ) else {
+        return Some(Vec::new());
+    };
+    let key = match channel.split_once("__:") {
+        Some((db, key)) if db.starts_with("__keyspace@") => key,
+        Some((db, _)) if db.starts_with("__keyevent@") => payload,
+        _ => return Some(Vec::new()),
+    };
+    Some(vec![key.to_owned()])
+}
+
+#[cfg(test)]
+mod test {
+    use super::{invalidated_keys, Cache, CacheStats};
+    use crate::lexi_data::{LexiData, SimpleString};
+
+    #[test]
+    fn it_evicts_the_least_recently_used_key() {
+        let mut cache = Cache::new(2);
+        cache.insert("a".to_owned(), 1.into());
+        cache.insert("b".to_owned(), 2.into());
+        assert_eq!(cache.get("a"), Some(1.into()));
+        cache.insert("c".to_owned(), 3.into());
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("c"), Some(3.into()));
+        cache.insert("a".to_owned(), 4.into());
+        assert_eq!(cache.entries.len(), 2);
+        cache.invalidate("a");
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 2,
+                misses: 2,
+                invalidations: 1,
+                evictions: 1,
+            }
+        );
+        assert_eq!(cache.stats().hit_rate(), 0.5);
+    }
+
+    #[test]
+    fn it_finds_invalidated_keys() {
+        let tests: [(Vec<LexiData>, Option<Vec<&str>>); 6] = [
+            (
+                vec![
+                    "invalidate".into(),
+                    LexiData::Array(vec!["a".into(), "b".into()]),
+                ],
+                Some(vec!["a", "b"]),
+            ),
+            (
+                vec!["invalidate".into(), LexiData::Simple(SimpleString::None)],
+                None,
+            ),
+            (
+                vec!["message".into(), "__keyspace@0__:a".into(), "set".into()],
+                Some(vec!["a"]),
+            ),
+            (
+                vec![
+                    "pmessage".into(),
+                    "__keyevent@*__:*".into(),
+                    "__keyevent@0__:del".into(),
+                    "b".into(),
+                ],
+                Some(vec!["b"]),
+            ),
+            (
+                vec!["message".into(), "news".into(), "hi".into()],
+                Some(vec![]),
+            ),
+            (vec![], Some(vec![])),
+        ];
+        for (push, exp) in tests {
+            let exp = exp.map(|keys| keys.into_iter().map(str::to_owned).collect());
+            assert_eq!(invalidated_keys(&push), exp);
+        }
+    }
+}
@@ -1,185 +1,2742 @@
-use tokio::net::{TcpSocket, TcpStream};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    hash::Hash,
+    io,
+    time::{Duration, Instant},
+};
 
-use crate::{builder::Builder, lexi_data::LexiData, parser::Parser};
+use bytes::{Buf, BytesMut};
+use futures::{future::BoxFuture, stream, Stream};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpSocket, TcpStream},
+};
 
-pub struct Client {
+use crate::{
+    cache::{invalidated_keys, Cache, CacheStats},
+    de::from_fields,
+    encoder::Encoder,
+    error::Error,
+    lexi_data::{unexpected, FromLexiData, LexiData, SimpleString, TryIntoLexiData},
+    parser::Parser,
+    pubsub::PubSub,
+    ser::to_optional_fields,
+    transaction::{Transaction, TxResults},
+};
+
+#[derive(Clone)]
+pub struct ClientConfig {
     addr: std::net::SocketAddr,
+    credentials: Option<(String, String)>,
+    cache_capacity: Option<usize>,
+}
+
+impl ClientConfig {
+    pub fn new(address: &str) -> anyhow::Result<Self> {
+        let addr = address.parse()?;
+        Ok(Self {
+            addr,
+            credentials: None,
+            cache_capacity: None,
+        })
+    }
+
+    pub fn addr(&self) -> std::net::SocketAddr {
+        self.addr
+    }
+
+    /// Authenticate with these credentials as part of `Client::connect`.
+    pub fn credentials(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.credentials = Some((username.into(), password.into()));
+        self
+    }
+
+    /// Keep up to `capacity` recent `get` replies in a client side cache.
+    ///
+    /// On connect the client asks the server to track the keys it reads
+    /// with `CLIENT TRACKING ON`, failing to connect if the server does not
+    /// know that, and drops cached keys as the server reports them
+    /// changed. Keys this client writes are dropped right away, and
+    /// `Client::exec` drops every key.
+    pub fn cache(mut self, capacity: usize) -> Self {
+        self.cache_capacity = Some(capacity);
+        self
+    }
+}
+
+impl fmt::Debug for ClientConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientConfig")
+            .field("addr", &self.addr)
+            .field(
+                "credentials",
+                &self
+                    .credentials
+                    .as_ref()
+                    .map(|(username, _)| (username, "<redacted>")),
+            )
+            .field("cache_capacity", &self.cache_capacity)
+            .finish()
+    }
+}
+
+/// The time to live of a key, as reported by `Client::ttl`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ttl {
+    /// The key does not exist.
+    Missing,
+    /// The key exists and never expires.
+    Persistent,
+    /// The key expires after this long.
+    Expires(Duration),
+}
+
+pub struct Client {
+    config: ClientConfig,
     stream: Option<TcpStream>,
+    write_buf: BytesMut,
+    read_buf: BytesMut,
+    native_mset: bool,
+    native_mget: bool,
+    native_mdel: bool,
+    native_bpop: bool,
+    native_bdeque: bool,
+    native_bqpop: bool,
+    /// source of every script that ran, keyed by SHA1, so `evalsha` can
+    /// resend a script the server has forgotten. Kept until
+    /// `forget_scripts`.
+    scripts: HashMap<String, String>,
+    cache: Option<Cache>,
+    /// set while a command waits for its reply, so a call dropped before
+    /// reading it is noticed by the next one
+    in_flight: bool,
 }
 
 impl Client {
     pub fn new(address: &str) -> anyhow::Result<Self> {
-        let addr = address.parse()?;
-        Ok(Self { addr, stream: None })
+        Ok(Self::with_config(ClientConfig::new(address)?))
+    }
+
+    pub fn with_config(config: ClientConfig) -> Self {
+        Self {
+            stream: None,
+            write_buf: BytesMut::with_capacity(4096),
+            read_buf: BytesMut::with_capacity(4096),
+            native_mset: true,
+            native_mget: true,
+            native_mdel: true,
+            native_bpop: true,
+            native_bdeque: true,
+            native_bqpop: true,
+            scripts: HashMap::new(),
+            cache: config.cache_capacity.map(Cache::new),
+            in_flight: false,
+            config,
+        }
     }
 
+    /// Connects to the server, authenticating straight away when the config
+    /// has credentials. A rejected login fails with `Error::AuthFailed` and
+    /// leaves the client disconnected.
     pub async fn connect(&mut self) -> anyhow::Result<()> {
         let socket = TcpSocket::new_v4()?;
-        let stream = socket.connect(self.addr).await?;
+        let stream = socket.connect(self.config.addr).await?;
+        self.disconnect();
         self.stream = Some(stream);
+        if let Some((username, password)) = self.config.credentials.clone() {
+            if let Err(e) = self.login(&username, &password).await {
+                self.disconnect();
+                return Err(e);
+            }
+        }
+        if self.cache.is_some() {
+            if let Err(e) = self.enable_invalidation().await {
+                self.disconnect();
+                return Err(e);
+            }
+        }
         Ok(())
     }
 
+    /// Whether the client has connected and the server has not hung up
+    /// since.
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    pub fn config(&self) -> &ClientConfig {
+        &self.config
+    }
+
+    /// Hit and miss counts of the client side cache, if it is enabled.
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(Cache::stats)
+    }
+
     pub async fn ping(&mut self) -> anyhow::Result<LexiData> {
-        let buf = Builder::new().add_ping().out();
-        let bytes = self.send_and_read(&buf).await?;
-        Self::parse(&bytes)
+        self.encoder().add_ping();
+        self.execute().await
     }
 
-    pub async fn auth(&mut self, username: &str, password: &str) -> anyhow::Result<LexiData> {
-        let buf = Builder::new()
-            .add_arr(2)
+    pub async fn auth(&mut self, username: &str, password: &str) -> anyhow::Result<()> {
+        self.discard_unread().await?;
+        self.login(username, password).await
+    }
+
+    /// `auth` on a fresh connection, for `connect`
+    async fn login(&mut self, username: &str, password: &str) -> anyhow::Result<()> {
+        self.encoder()
+            .add_arr(3)
             .add_bulk("AUTH")
             .add_bulk(username)
-            .add_bulk(password)
-            .out();
-        let bytes = self.send_and_read(&buf).await?;
-        Self::parse(&bytes)
+            .add_bulk(password);
+        match self.exchange().await? {
+            LexiData::Simple(SimpleString::Ok) => Ok(()),
+            LexiData::Error(e) => Err(Error::AuthFailed(e).into()),
+            other => Err(Error::AuthFailed(format!("unexpected response {}", other)).into()),
+        }
     }
 
     pub async fn keys(&mut self) -> anyhow::Result<LexiData> {
-        let buf = Builder::new()
-            .add_bulk("KEYS")
-            .out();
-        let bytes = self.send_and_read(&buf).await?;
-        Self::parse(&bytes)
+        self.encoder().add_bulk("KEYS");
+        self.execute().await
+    }
+
+    /// Returns every key matching a glob style `pattern` in one response.
+    /// Prefer `scan` on large keyspaces.
+    pub async fn keys_matching(&mut self, pattern: &str) -> anyhow::Result<Vec<String>> {
+        self.encoder().add_arr(2).add_bulk("KEYS").add_bulk(pattern);
+        let data = self.execute().await?;
+        Vec::from_lexi_data(data)
+    }
+
+    /// Streams every key matching a glob style `pattern`, fetching them with
+    /// `SCAN` in pages of roughly `count` keys. The stream ends after the
+    /// first error.
+    pub fn scan<'a>(
+        &'a mut self,
+        pattern: &'a str,
+        count: usize,
+    ) -> impl Stream<Item = anyhow::Result<String>> + 'a {
+        let page: std::vec::IntoIter<String> = Vec::new().into_iter();
+        stream::unfold(
+            (self, Some(0), page),
+            move |(client, mut cursor, mut page)| async move {
+                loop {
+                    if let Some(key) = page.next() {
+                        return Some((Ok(key), (client, cursor, page)));
+                    }
+                    match client.scan_page(cursor?, pattern, count).await {
+                        Ok((next, keys)) => {
+                            cursor = if next == 0 { None } else { Some(next) };
+                            page = keys.into_iter();
+                        }
+                        Err(e) => return Some((Err(e), (client, None, page))),
+                    }
+                }
+            },
+        )
+    }
+
+    async fn scan_page(
+        &mut self,
+        cursor: u64,
+        pattern: &str,
+        count: usize,
+    ) -> anyhow::Result<(u64, Vec<String>)> {
+        let mut cursor_fmt = itoa::Buffer::new();
+        let mut count_fmt = itoa::Buffer::new();
+        self.encoder()
+            .add_arr(6)
+            .add_bulk("SCAN")
+            .add_bulk(cursor_fmt.format(cursor))
+            .add_bulk("MATCH")
+            .add_bulk(pattern)
+            .add_bulk("COUNT")
+            .add_bulk(count_fmt.format(count));
+        let [cursor, keys] = match self.execute().await? {
+            LexiData::Array(arr) => <[LexiData; 2]>::try_from(arr)
+                .map_err(|arr| unexpected("cursor and keys", LexiData::Array(arr)))?,
+            other => return Err(unexpected("cursor and keys", other)),
+        };
+        let cursor = match cursor {
+            LexiData::Int(i) => u64::try_from(i)?,
+            LexiData::Bulk(s) => s.parse()?,
+            other => return Err(unexpected("cursor", other)),
+        };
+        Ok((cursor, Vec::from_lexi_data(keys)?))
     }
 
     pub async fn set(
         &mut self,
         key: impl Into<LexiData>,
-        value: impl Into<LexiData>,
+        value: impl TryIntoLexiData,
     ) -> anyhow::Result<LexiData> {
-        let buf = Builder::new()
+        let value = value.try_into_lexi_data()?;
+        let key = self.invalidate(key);
+        self.encoder()
             .add_arr(3)
             .add_bulk("SET")
             .add_impl_lexi_data(key)
-            .add_impl_lexi_data(value)
-            .out();
-        let bytes = self.send_and_read(&buf).await?;
-        Self::parse(&bytes)
+            .add_impl_lexi_data(value);
+        self.execute().await
     }
 
-    pub async fn get(&mut self, key: impl Into<LexiData>) -> anyhow::Result<LexiData> {
-        let buf = Builder::new()
-            .add_arr(2)
-            .add_bulk("GET")
-            .add_impl_lexi_data(key)
-            .out();
-        let bytes = self.send_and_read(&buf).await?;
-        Self::parse(&bytes)
+    /// Gets `key`, answering from the client side cache when it is enabled
+    /// and holds the key.
+    pub async fn get<T: FromLexiData>(&mut self, key: impl Into<LexiData>) -> anyhow::Result<T> {
+        let key = key.into();
+        let cache_key = self.cache.as_ref().and_then(|_| cache_key(&key));
+        if let Some(cache_key) = &cache_key {
+            self.read_pushes()?;
+            // nothing is cached while disconnected, so let the GET fail
+            if self.stream.is_some() {
+                if let Some(data) = self.cache.as_mut().and_then(|cache| cache.get(cache_key)) {
+                    return T::from_lexi_data(data);
+                }
+            }
+        }
+        self.encoder().add_arr(2).add_bulk("GET").add_data(&key);
+        let data = self.execute().await?;
+        if let (Some(cache_key), Some(cache)) = (cache_key, &mut self.cache) {
+            if !data.is_error() {
+                cache.insert(cache_key, data.clone());
+            }
+        }
+        T::from_lexi_data(data)
     }
 
     pub async fn del(&mut self, key: impl Into<LexiData>) -> anyhow::Result<LexiData> {
-        let buf = Builder::new()
+        let key = self.invalidate(key);
+        self.encoder()
             .add_arr(2)
             .add_bulk("DEL")
+            .add_impl_lexi_data(key);
+        self.execute().await
+    }
+
+    /// Sets `key` only if it does not exist yet, returning whether it was set.
+    pub async fn set_nx(
+        &mut self,
+        key: impl Into<LexiData>,
+        value: impl TryIntoLexiData,
+    ) -> anyhow::Result<bool> {
+        let value = value.try_into_lexi_data()?;
+        self.encode_set_with(key, value, "NX");
+        let data = self.execute().await?;
+        bool::from_lexi_data(data)
+    }
+
+    /// Sets `key` only if it already exists, returning whether it was set.
+    pub async fn set_xx(
+        &mut self,
+        key: impl Into<LexiData>,
+        value: impl TryIntoLexiData,
+    ) -> anyhow::Result<bool> {
+        let value = value.try_into_lexi_data()?;
+        self.encode_set_with(key, value, "XX");
+        let data = self.execute().await?;
+        bool::from_lexi_data(data)
+    }
+
+    /// Sets `key` and returns the value it held before, or `None` if it did
+    /// not exist.
+    pub async fn set_get<T: FromLexiData>(
+        &mut self,
+        key: impl Into<LexiData>,
+        value: impl TryIntoLexiData,
+    ) -> anyhow::Result<Option<T>> {
+        let value = value.try_into_lexi_data()?;
+        self.encode_set_with(key, value, "GET");
+        let data = self.execute().await?;
+        Option::from_lexi_data(data)
+    }
+
+    /// Sets `key` to `value`, expiring it after `ttl`. Expiry times have
+    /// millisecond precision.
+    pub async fn set_ex(
+        &mut self,
+        key: impl Into<LexiData>,
+        value: impl TryIntoLexiData,
+        ttl: Duration,
+    ) -> anyhow::Result<()> {
+        let value = value.try_into_lexi_data()?;
+        let key = self.invalidate(key);
+        let ms = millis(ttl)?;
+        self.encoder()
+            .add_arr(5)
+            .add_bulk("SET")
+            .add_impl_lexi_data(key)
+            .add_impl_lexi_data(value)
+            .add_bulk("PX")
+            .add_int(ms);
+        self.execute().await?.into_result().map(|_| ())
+    }
+
+    /// Expires `key` after `ttl`, returning false if the key does not exist.
+    pub async fn expire(
+        &mut self,
+        key: impl Into<LexiData>,
+        ttl: Duration,
+    ) -> anyhow::Result<bool> {
+        let key = self.invalidate(key);
+        let ms = millis(ttl)?;
+        self.encoder()
+            .add_arr(3)
+            .add_bulk("PEXPIRE")
             .add_impl_lexi_data(key)
-            .out();
-        let bytes = self.send_and_read(&buf).await?;
-        Self::parse(&bytes)
+            .add_int(ms);
+        let data = self.execute().await?;
+        bool::from_lexi_data(data)
+    }
+
+    pub async fn ttl(&mut self, key: impl Into<LexiData>) -> anyhow::Result<Ttl> {
+        self.encoder()
+            .add_arr(2)
+            .add_bulk("PTTL")
+            .add_impl_lexi_data(key);
+        match i64::from_lexi_data(self.execute().await?)? {
+            -2 => Ok(Ttl::Missing),
+            -1 => Ok(Ttl::Persistent),
+            ms => Ok(Ttl::Expires(Duration::from_millis(u64::try_from(ms)?))),
+        }
+    }
+
+    /// Removes the expiry from `key`, returning false if the key does not
+    /// exist or had no expiry.
+    pub async fn persist(&mut self, key: impl Into<LexiData>) -> anyhow::Result<bool> {
+        self.encoder()
+            .add_arr(2)
+            .add_bulk("PERSIST")
+            .add_impl_lexi_data(key);
+        let data = self.execute().await?;
+        bool::from_lexi_data(data)
     }
 
-    pub async fn push(&mut self, value: impl Into<LexiData>) -> anyhow::Result<LexiData> {
-        let buf = Builder::new()
+    /// Atomically adds one to the integer at `key`, treating a missing key
+    /// as 0, and returns the new value.
+    pub async fn incr(&mut self, key: impl Into<LexiData>) -> anyhow::Result<i64> {
+        let key = self.invalidate(key);
+        self.encoder()
+            .add_arr(2)
+            .add_bulk("INCR")
+            .add_impl_lexi_data(key);
+        let data = self.execute().await?;
+        i64::from_lexi_data(data)
+    }
+
+    /// Atomically subtracts one from the integer at `key`, treating a
+    /// missing key as 0, and returns the new value.
+    pub async fn decr(&mut self, key: impl Into<LexiData>) -> anyhow::Result<i64> {
+        let key = self.invalidate(key);
+        self.encoder()
+            .add_arr(2)
+            .add_bulk("DECR")
+            .add_impl_lexi_data(key);
+        let data = self.execute().await?;
+        i64::from_lexi_data(data)
+    }
+
+    pub async fn incrby(&mut self, key: impl Into<LexiData>, by: i64) -> anyhow::Result<i64> {
+        let key = self.invalidate(key);
+        self.encoder()
+            .add_arr(3)
+            .add_bulk("INCRBY")
+            .add_impl_lexi_data(key)
+            .add_int(by);
+        let data = self.execute().await?;
+        i64::from_lexi_data(data)
+    }
+
+    pub async fn decrby(&mut self, key: impl Into<LexiData>, by: i64) -> anyhow::Result<i64> {
+        let key = self.invalidate(key);
+        self.encoder()
+            .add_arr(3)
+            .add_bulk("DECRBY")
+            .add_impl_lexi_data(key)
+            .add_int(by);
+        let data = self.execute().await?;
+        i64::from_lexi_data(data)
+    }
+
+    pub async fn incrbyfloat(&mut self, key: impl Into<LexiData>, by: f64) -> anyhow::Result<f64> {
+        let key = self.invalidate(key);
+        self.encoder()
+            .add_arr(3)
+            .add_bulk("INCRBYFLOAT")
+            .add_impl_lexi_data(key)
+            .add_double(by);
+        let data = self.execute().await?;
+        f64::from_lexi_data(data)
+    }
+
+    /// Sets every pair with a single `MSET`, or a pipeline of `SET`s when
+    /// the server does not know `MSET`.
+    pub async fn mset<K, V>(&mut self, pairs: &[(K, V)]) -> anyhow::Result<()>
+    where
+        K: Into<LexiData> + Clone,
+        V: TryIntoLexiData + Clone,
+    {
+        if pairs.is_empty() {
+            return Ok(());
+        }
+        let pairs = pairs
+            .iter()
+            .map(|(key, value)| Ok((key.clone().into(), value.clone().try_into_lexi_data()?)))
+            .collect::<anyhow::Result<Vec<(LexiData, LexiData)>>>()?;
+        for (key, _) in &pairs {
+            self.invalidate(key.clone());
+        }
+        if self.native_mset {
+            let mut enc = self.encoder();
+            enc.add_arr(1 + pairs.len() * 2).add_bulk("MSET");
+            for (key, value) in &pairs {
+                enc.add_data(key).add_data(value);
+            }
+            match self.execute().await? {
+                LexiData::Error(e) if is_unknown_command(&e) => self.native_mset = false,
+                data => return data.into_result().map(|_| ()),
+            }
+        }
+        self.write_buf.clear();
+        for (key, value) in &pairs {
+            Encoder::new(&mut self.write_buf)
+                .add_arr(3)
+                .add_bulk("SET")
+                .add_data(key)
+                .add_data(value);
+        }
+        for data in self.execute_many(pairs.len()).await? {
+            data.into_result()?;
+        }
+        Ok(())
+    }
+
+    /// Gets every key with a single `MGET`, or a pipeline of `GET`s when the
+    /// server does not know `MGET`. Missing keys come back as `None`.
+    pub async fn mget<K, T>(&mut self, keys: &[K]) -> anyhow::Result<Vec<Option<T>>>
+    where
+        K: Into<LexiData> + Clone,
+        T: FromLexiData,
+    {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        if self.native_mget {
+            self.encode_keys("MGET", keys);
+            match self.execute().await? {
+                LexiData::Error(e) if is_unknown_command(&e) => self.native_mget = false,
+                data => return Vec::from_lexi_data(data),
+            }
+        }
+        self.write_buf.clear();
+        for key in keys {
+            Encoder::new(&mut self.write_buf)
+                .add_arr(2)
+                .add_bulk("GET")
+                .add_impl_lexi_data(key.clone());
+        }
+        let responses = self.execute_many(keys.len()).await?;
+        responses.into_iter().map(Option::from_lexi_data).collect()
+    }
+
+    /// Deletes every key with a single `MDEL`, or a pipeline of `DEL`s when
+    /// the server does not know `MDEL`, returning how many keys were deleted.
+    pub async fn mdel<K>(&mut self, keys: &[K]) -> anyhow::Result<usize>
+    where
+        K: Into<LexiData> + Clone,
+    {
+        if keys.is_empty() {
+            return Ok(0);
+        }
+        for key in keys {
+            self.invalidate(key.clone());
+        }
+        if self.native_mdel {
+            self.encode_keys("MDEL", keys);
+            match self.execute().await? {
+                LexiData::Error(e) if is_unknown_command(&e) => self.native_mdel = false,
+                data => return usize::from_lexi_data(data),
+            }
+        }
+        self.write_buf.clear();
+        for key in keys {
+            Encoder::new(&mut self.write_buf)
+                .add_arr(2)
+                .add_bulk("DEL")
+                .add_impl_lexi_data(key.clone());
+        }
+        let mut deleted = 0;
+        for data in self.execute_many(keys.len()).await? {
+            deleted += match data.into_result()? {
+                LexiData::Int(n) => usize::try_from(n)?,
+                LexiData::Simple(SimpleString::Ok) => 1,
+                _ => 0,
+            };
+        }
+        Ok(deleted)
+    }
+
+    pub async fn push(&mut self, value: impl TryIntoLexiData) -> anyhow::Result<LexiData> {
+        let value = value.try_into_lexi_data()?;
+        self.encoder()
             .add_arr(2)
             .add_bulk("PUSH")
-            .add_impl_lexi_data(value)
-            .out();
-        let bytes = self.send_and_read(&buf).await?;
-        Self::parse(&bytes)
+            .add_impl_lexi_data(value);
+        self.execute().await
     }
 
     pub async fn pop(&mut self) -> anyhow::Result<LexiData> {
-        let buf = Builder::new().add_bulk("POP").out();
-        let bytes = self.send_and_read(&buf).await?;
-        Self::parse(&bytes)
+        self.encoder().add_bulk("POP");
+        self.execute().await
     }
 
-    pub async fn enque(&mut self, value: impl Into<LexiData>) -> anyhow::Result<LexiData> {
-        let buf = Builder::new()
+    pub async fn enque(&mut self, value: impl TryIntoLexiData) -> anyhow::Result<LexiData> {
+        let value = value.try_into_lexi_data()?;
+        self.encoder()
             .add_arr(2)
             .add_bulk("ENQUE")
-            .add_impl_lexi_data(value)
-            .out();
-        let bytes = self.send_and_read(&buf).await?;
-        Self::parse(&bytes)
+            .add_impl_lexi_data(value);
+        self.execute().await
     }
 
     pub async fn deque(&mut self) -> anyhow::Result<LexiData> {
-        let buf = Builder::new().add_bulk("DEQUE").out();
-        let bytes = self.send_and_read(&buf).await?;
-        Self::parse(&bytes)
+        self.encoder().add_bulk("DEQUE");
+        self.execute().await
+    }
+
+    /// Pushes `value` onto the front of the list at `key`, returning the new
+    /// length. `lpush` and `lpop` together use the list as a stack.
+    pub async fn lpush(
+        &mut self,
+        key: impl Into<LexiData>,
+        value: impl TryIntoLexiData,
+    ) -> anyhow::Result<usize> {
+        let value = value.try_into_lexi_data()?;
+        self.encoder()
+            .add_arr(3)
+            .add_bulk("LPUSH")
+            .add_impl_lexi_data(key)
+            .add_impl_lexi_data(value);
+        let data = self.execute().await?;
+        usize::from_lexi_data(data)
+    }
+
+    /// Pushes `value` onto the back of the list at `key`, returning the new
+    /// length.
+    pub async fn rpush(
+        &mut self,
+        key: impl Into<LexiData>,
+        value: impl TryIntoLexiData,
+    ) -> anyhow::Result<usize> {
+        let value = value.try_into_lexi_data()?;
+        self.encoder()
+            .add_arr(3)
+            .add_bulk("RPUSH")
+            .add_impl_lexi_data(key)
+            .add_impl_lexi_data(value);
+        let data = self.execute().await?;
+        usize::from_lexi_data(data)
+    }
+
+    /// Removes and returns the front of the list at `key`, or `None` if the
+    /// list is empty.
+    pub async fn lpop<T: FromLexiData>(
+        &mut self,
+        key: impl Into<LexiData>,
+    ) -> anyhow::Result<Option<T>> {
+        self.encoder()
+            .add_arr(2)
+            .add_bulk("LPOP")
+            .add_impl_lexi_data(key);
+        let data = self.execute().await?;
+        Option::from_lexi_data(data)
+    }
+
+    /// Removes and returns the back of the list at `key`, or `None` if the
+    /// list is empty.
+    pub async fn rpop<T: FromLexiData>(
+        &mut self,
+        key: impl Into<LexiData>,
+    ) -> anyhow::Result<Option<T>> {
+        self.encoder()
+            .add_arr(2)
+            .add_bulk("RPOP")
+            .add_impl_lexi_data(key);
+        let data = self.execute().await?;
+        Option::from_lexi_data(data)
+    }
+
+    pub async fn llen(&mut self, key: impl Into<LexiData>) -> anyhow::Result<usize> {
+        self.encoder()
+            .add_arr(2)
+            .add_bulk("LLEN")
+            .add_impl_lexi_data(key);
+        let data = self.execute().await?;
+        usize::from_lexi_data(data)
+    }
+
+    /// Returns the element at `index` without removing it. Negative indexes
+    /// count from the back of the list.
+    pub async fn lindex<T: FromLexiData>(
+        &mut self,
+        key: impl Into<LexiData>,
+        index: i64,
+    ) -> anyhow::Result<Option<T>> {
+        self.encoder()
+            .add_arr(3)
+            .add_bulk("LINDEX")
+            .add_impl_lexi_data(key)
+            .add_int(index);
+        let data = self.execute().await?;
+        Option::from_lexi_data(data)
+    }
+
+    /// Returns the elements from `start` to `stop` inclusive without removing
+    /// them. Negative indexes count from the back, so `0, -1` is everything.
+    pub async fn lrange<T: FromLexiData>(
+        &mut self,
+        key: impl Into<LexiData>,
+        start: i64,
+        stop: i64,
+    ) -> anyhow::Result<Vec<T>> {
+        self.encoder()
+            .add_arr(4)
+            .add_bulk("LRANGE")
+            .add_impl_lexi_data(key)
+            .add_int(start)
+            .add_int(stop);
+        let data = self.execute().await?;
+        Vec::from_lexi_data(data)
+    }
+
+    /// Pops the stack, waiting up to `timeout` for an element if it is
+    /// empty. Returns `None` if nothing arrived in time, and a zero
+    /// `timeout` does not wait at all.
+    pub async fn bpop<T: FromLexiData>(&mut self, timeout: Duration) -> anyhow::Result<Option<T>> {
+        self.blocking_pop(&Pop::Stack, timeout).await
+    }
+
+    /// Dequeues from the queue, waiting up to `timeout` for an element if it
+    /// is empty. Returns `None` if nothing arrived in time, and a zero
+    /// `timeout` does not wait at all.
+    pub async fn bdeque<T: FromLexiData>(
+        &mut self,
+        timeout: Duration,
+    ) -> anyhow::Result<Option<T>> {
+        self.blocking_pop(&Pop::Queue, timeout).await
+    }
+
+    /// Streams elements as they are dequeued, waiting for new ones whenever
+    /// the queue is empty. The stream ends after the first error.
+    ///
+    /// Dropping the stream while it waits, say in a `tokio::select!`, leaves
+    /// the wait running on the server. The client then reconnects before
+    /// its next command, which ends the wait without losing an element.
+    pub fn deque_stream<'a, T: FromLexiData + 'a>(
+        &'a mut self,
+    ) -> impl Stream<Item = anyhow::Result<T>> + 'a {
+        self.pop_stream(Pop::Queue)
+    }
+
+    /// Adds `value` to the back of the queue named `queue`, returning the new
+    /// length.
+    pub async fn qpush(
+        &mut self,
+        queue: impl Into<LexiData>,
+        value: impl TryIntoLexiData,
+    ) -> anyhow::Result<usize> {
+        let value = value.try_into_lexi_data()?;
+        self.encoder()
+            .add_arr(3)
+            .add_bulk("QPUSH")
+            .add_impl_lexi_data(queue)
+            .add_impl_lexi_data(value);
+        let data = self.execute().await?;
+        usize::from_lexi_data(data)
+    }
+
+    /// Removes and returns the front of `queue`, or `None` if it is empty.
+    pub async fn qpop<T: FromLexiData>(
+        &mut self,
+        queue: impl Into<LexiData>,
+    ) -> anyhow::Result<Option<T>> {
+        self.encoder()
+            .add_arr(2)
+            .add_bulk("QPOP")
+            .add_impl_lexi_data(queue);
+        let data = self.execute().await?;
+        Option::from_lexi_data(data)
+    }
+
+    /// Pops the front of `queue`, waiting up to `timeout` for an element if
+    /// it is empty. Returns `None` if nothing arrived in time, and a zero
+    /// `timeout` does not wait at all.
+    pub async fn bqpop<T: FromLexiData>(
+        &mut self,
+        queue: impl Into<LexiData>,
+        timeout: Duration,
+    ) -> anyhow::Result<Option<T>> {
+        self.blocking_pop(&Pop::Named(queue.into()), timeout).await
+    }
+
+    /// Streams elements as they are popped from `queue`, waiting for new
+    /// ones whenever it is empty. The stream ends after the first error.
+    /// Dropping it while it waits reconnects the client, as with
+    /// `deque_stream`.
+    pub fn qpop_stream<'a, T: FromLexiData + 'a>(
+        &'a mut self,
+        queue: impl Into<LexiData>,
+    ) -> impl Stream<Item = anyhow::Result<T>> + 'a {
+        self.pop_stream(Pop::Named(queue.into()))
+    }
+
+    /// Returns the front of `queue` without removing it.
+    pub async fn qpeek<T: FromLexiData>(
+        &mut self,
+        queue: impl Into<LexiData>,
+    ) -> anyhow::Result<Option<T>> {
+        self.encoder()
+            .add_arr(2)
+            .add_bulk("QPEEK")
+            .add_impl_lexi_data(queue);
+        let data = self.execute().await?;
+        Option::from_lexi_data(data)
+    }
+
+    pub async fn qlen(&mut self, queue: impl Into<LexiData>) -> anyhow::Result<usize> {
+        self.encoder()
+            .add_arr(2)
+            .add_bulk("QLEN")
+            .add_impl_lexi_data(queue);
+        let data = self.execute().await?;
+        usize::from_lexi_data(data)
+    }
+
+    /// Returns the elements from `start` to `stop` inclusive, counted from
+    /// the front of `queue`, without removing them.
+    pub async fn qrange<T: FromLexiData>(
+        &mut self,
+        queue: impl Into<LexiData>,
+        start: i64,
+        stop: i64,
+    ) -> anyhow::Result<Vec<T>> {
+        self.encoder()
+            .add_arr(4)
+            .add_bulk("QRANGE")
+            .add_impl_lexi_data(queue)
+            .add_int(start)
+            .add_int(stop);
+        let data = self.execute().await?;
+        Vec::from_lexi_data(data)
     }
 
     pub async fn zset(&mut self, value: impl Into<LexiData>) -> anyhow::Result<LexiData> {
-        let buf = Builder::new()
+        self.encoder()
             .add_arr(2)
             .add_bulk("ZSET")
-            .add_impl_lexi_data(value)
-            .out();
-        let bytes = self.send_and_read(&buf).await?;
-        Self::parse(&bytes)
+            .add_impl_lexi_data(value);
+        self.execute().await
     }
 
     pub async fn zhas(&mut self, value: impl Into<LexiData>) -> anyhow::Result<LexiData> {
-        let buf = Builder::new()
+        self.encoder()
             .add_arr(2)
             .add_bulk("ZHAS")
-            .add_impl_lexi_data(value)
-            .out();
-        let bytes = self.send_and_read(&buf).await?;
-        Self::parse(&bytes)
+            .add_impl_lexi_data(value);
+        self.execute().await
     }
 
     pub async fn zdel(&mut self, value: impl Into<LexiData>) -> anyhow::Result<LexiData> {
-        let buf = Builder::new()
+        self.encoder()
             .add_arr(2)
             .add_bulk("ZDEL")
-            .add_impl_lexi_data(value)
-            .out();
-        let bytes = self.send_and_read(&buf).await?;
-        Self::parse(&bytes)
+            .add_impl_lexi_data(value);
+        self.execute().await
+    }
+
+    /// Adds `members` to the set at `key`, returning how many were not
+    /// already in it.
+    pub async fn sadd<M>(
+        &mut self,
+        key: impl Into<LexiData>,
+        members: &[M],
+    ) -> anyhow::Result<usize>
+    where
+        M: Into<LexiData> + Clone,
+    {
+        self.encode_key_with("SADD", key, members);
+        let data = self.execute().await?;
+        usize::from_lexi_data(data)
+    }
+
+    /// Removes `members` from the set at `key`, returning how many were in it.
+    pub async fn srem<M>(
+        &mut self,
+        key: impl Into<LexiData>,
+        members: &[M],
+    ) -> anyhow::Result<usize>
+    where
+        M: Into<LexiData> + Clone,
+    {
+        self.encode_key_with("SREM", key, members);
+        let data = self.execute().await?;
+        usize::from_lexi_data(data)
+    }
+
+    pub async fn sismember(
+        &mut self,
+        key: impl Into<LexiData>,
+        member: impl Into<LexiData>,
+    ) -> anyhow::Result<bool> {
+        self.encoder()
+            .add_arr(3)
+            .add_bulk("SISMEMBER")
+            .add_impl_lexi_data(key)
+            .add_impl_lexi_data(member);
+        let data = self.execute().await?;
+        bool::from_lexi_data(data)
     }
 
-    fn parse(buf: &Vec<u8>) -> anyhow::Result<LexiData> {
-        let mut p = Parser::new(buf);
-        p.parse()
+    pub async fn scard(&mut self, key: impl Into<LexiData>) -> anyhow::Result<usize> {
+        self.encoder()
+            .add_arr(2)
+            .add_bulk("SCARD")
+            .add_impl_lexi_data(key);
+        let data = self.execute().await?;
+        usize::from_lexi_data(data)
+    }
+
+    pub async fn smembers<T>(&mut self, key: impl Into<LexiData>) -> anyhow::Result<HashSet<T>>
+    where
+        T: FromLexiData + Eq + Hash,
+    {
+        self.encoder()
+            .add_arr(2)
+            .add_bulk("SMEMBERS")
+            .add_impl_lexi_data(key);
+        let data = self.execute().await?;
+        HashSet::from_lexi_data(data)
+    }
+
+    /// Returns a random member of the set at `key` without removing it, or
+    /// `None` if the set is empty.
+    pub async fn srandmember<T: FromLexiData>(
+        &mut self,
+        key: impl Into<LexiData>,
+    ) -> anyhow::Result<Option<T>> {
+        self.encoder()
+            .add_arr(2)
+            .add_bulk("SRANDMEMBER")
+            .add_impl_lexi_data(key);
+        let data = self.execute().await?;
+        Option::from_lexi_data(data)
+    }
+
+    /// Returns the members found in any of the sets at `keys`.
+    pub async fn sunion<K, T>(&mut self, keys: &[K]) -> anyhow::Result<HashSet<T>>
+    where
+        K: Into<LexiData> + Clone,
+        T: FromLexiData + Eq + Hash,
+    {
+        self.encode_keys("SUNION", keys);
+        let data = self.execute().await?;
+        HashSet::from_lexi_data(data)
+    }
+
+    /// Returns the members found in every one of the sets at `keys`.
+    pub async fn sinter<K, T>(&mut self, keys: &[K]) -> anyhow::Result<HashSet<T>>
+    where
+        K: Into<LexiData> + Clone,
+        T: FromLexiData + Eq + Hash,
+    {
+        self.encode_keys("SINTER", keys);
+        let data = self.execute().await?;
+        HashSet::from_lexi_data(data)
+    }
+
+    /// Returns the members of the set at the first key that are in none of
+    /// the sets at the other keys.
+    pub async fn sdiff<K, T>(&mut self, keys: &[K]) -> anyhow::Result<HashSet<T>>
+    where
+        K: Into<LexiData> + Clone,
+        T: FromLexiData + Eq + Hash,
+    {
+        self.encode_keys("SDIFF", keys);
+        let data = self.execute().await?;
+        HashSet::from_lexi_data(data)
     }
 
-    async fn send_and_read(&mut self, buf: &Vec<u8>) -> anyhow::Result<Vec<u8>> {
-        self.send(&buf).await?;
-        self.read().await
+    /// Adds `member` to the sorted set at `key` with `score`, or updates its
+    /// score. Returns true if the member is new.
+    pub async fn zadd(
+        &mut self,
+        key: impl Into<LexiData>,
+        score: f64,
+        member: impl Into<LexiData>,
+    ) -> anyhow::Result<bool> {
+        self.encoder()
+            .add_arr(4)
+            .add_bulk("ZADD")
+            .add_impl_lexi_data(key)
+            .add_double(score)
+            .add_impl_lexi_data(member);
+        let data = self.execute().await?;
+        bool::from_lexi_data(data)
+    }
+
+    /// Adds `by` to the score of `member`, adding it with a score of `by` if
+    /// it is missing, and returns the new score.
+    pub async fn zincrby(
+        &mut self,
+        key: impl Into<LexiData>,
+        by: f64,
+        member: impl Into<LexiData>,
+    ) -> anyhow::Result<f64> {
+        self.encoder()
+            .add_arr(4)
+            .add_bulk("ZINCRBY")
+            .add_impl_lexi_data(key)
+            .add_double(by)
+            .add_impl_lexi_data(member);
+        let data = self.execute().await?;
+        f64::from_lexi_data(data)
+    }
+
+    pub async fn zscore(
+        &mut self,
+        key: impl Into<LexiData>,
+        member: impl Into<LexiData>,
+    ) -> anyhow::Result<Option<f64>> {
+        self.encoder()
+            .add_arr(3)
+            .add_bulk("ZSCORE")
+            .add_impl_lexi_data(key)
+            .add_impl_lexi_data(member);
+        let data = self.execute().await?;
+        Option::from_lexi_data(data)
+    }
+
+    /// Returns the 0 based position of `member` when ordered by ascending
+    /// score, or `None` if it is not in the set.
+    pub async fn zrank(
+        &mut self,
+        key: impl Into<LexiData>,
+        member: impl Into<LexiData>,
+    ) -> anyhow::Result<Option<usize>> {
+        self.encoder()
+            .add_arr(3)
+            .add_bulk("ZRANK")
+            .add_impl_lexi_data(key)
+            .add_impl_lexi_data(member);
+        let data = self.execute().await?;
+        Option::from_lexi_data(data)
+    }
+
+    /// Removes `members` from the sorted set at `key`, returning how many
+    /// were in it.
+    pub async fn zrem<M>(
+        &mut self,
+        key: impl Into<LexiData>,
+        members: &[M],
+    ) -> anyhow::Result<usize>
+    where
+        M: Into<LexiData> + Clone,
+    {
+        self.encode_key_with("ZREM", key, members);
+        let data = self.execute().await?;
+        usize::from_lexi_data(data)
+    }
+
+    pub async fn zcard(&mut self, key: impl Into<LexiData>) -> anyhow::Result<usize> {
+        self.encoder()
+            .add_arr(2)
+            .add_bulk("ZCARD")
+            .add_impl_lexi_data(key);
+        let data = self.execute().await?;
+        usize::from_lexi_data(data)
+    }
+
+    /// Returns the members ranked `start` to `stop` inclusive, with their
+    /// scores, in ascending score order. Negative ranks count from the
+    /// highest score, so `0, -1` is the whole set.
+    pub async fn zrange(
+        &mut self,
+        key: impl Into<LexiData>,
+        start: i64,
+        stop: i64,
+    ) -> anyhow::Result<Vec<(String, f64)>> {
+        self.encoder()
+            .add_arr(5)
+            .add_bulk("ZRANGE")
+            .add_impl_lexi_data(key)
+            .add_int(start)
+            .add_int(stop)
+            .add_bulk("WITHSCORES");
+        let data = self.execute().await?;
+        scored_members(data)
     }
 
-    async fn send(&mut self, buf: &Vec<u8>) -> anyhow::Result<()> {
-        match &self.stream {
-            Some(stream) => loop {
-                stream.writable().await?;
-                match stream.try_write(buf) {
-                    Ok(_) => break,
-                    Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => continue,
-                    Err(e) => return Err(e.into()),
+    /// Returns the members scoring between `min` and `max` inclusive, with
+    /// their scores, in ascending score order. `limit` is an offset and a
+    /// count into the matching members. Use the infinities for open ranges.
+    pub async fn zrangebyscore(
+        &mut self,
+        key: impl Into<LexiData>,
+        min: f64,
+        max: f64,
+        limit: Option<(usize, usize)>,
+    ) -> anyhow::Result<Vec<(String, f64)>> {
+        let limit = match limit {
+            Some((offset, count)) => Some((i64::try_from(offset)?, i64::try_from(count)?)),
+            None => None,
+        };
+        let mut enc = self.encoder();
+        enc.add_arr(if limit.is_some() { 8 } else { 5 })
+            .add_bulk("ZRANGEBYSCORE")
+            .add_impl_lexi_data(key)
+            .add_double(min)
+            .add_double(max)
+            .add_bulk("WITHSCORES");
+        if let Some((offset, count)) = limit {
+            enc.add_bulk("LIMIT").add_int(offset).add_int(count);
+        }
+        let data = self.execute().await?;
+        scored_members(data)
+    }
+
+    /// Sets `field` in the hash at `key`, returning true if the field is new.
+    pub async fn hset(
+        &mut self,
+        key: impl Into<LexiData>,
+        field: impl Into<LexiData>,
+        value: impl TryIntoLexiData,
+    ) -> anyhow::Result<bool> {
+        let value = value.try_into_lexi_data()?;
+        self.encoder()
+            .add_arr(4)
+            .add_bulk("HSET")
+            .add_impl_lexi_data(key)
+            .add_impl_lexi_data(field)
+            .add_impl_lexi_data(value);
+        let data = self.execute().await?;
+        bool::from_lexi_data(data)
+    }
+
+    /// Stores each field of a serde struct (or map) as a field of the hash
+    /// at `key`, returning how many fields are new. Fields holding `None`
+    /// are deleted from the hash with a pipelined `HDEL`. See
+    /// `lexi_rs::to_fields` for the supported field types.
+    pub async fn hset_struct<T: Serialize + ?Sized>(
+        &mut self,
+        key: impl Into<LexiData>,
+        value: &T,
+    ) -> anyhow::Result<usize> {
+        let fields = to_optional_fields(value)?;
+        let set: Vec<_> = fields
+            .iter()
+            .filter_map(|(field, value)| Some((field, value.as_ref()?)))
+            .collect();
+        let unset: Vec<_> = fields
+            .iter()
+            .filter(|(_, value)| value.is_none())
+            .map(|(field, _)| field)
+            .collect();
+        let key = key.into();
+        let mut enc = self.encoder();
+        if !set.is_empty() {
+            enc.add_arr(2 + set.len() * 2)
+                .add_bulk("HSET")
+                .add_data(&key);
+            for (field, value) in &set {
+                enc.add_bulk(field).add_data(value);
+            }
+        }
+        if !unset.is_empty() {
+            enc.add_arr(2 + unset.len()).add_bulk("HDEL").add_data(&key);
+            for field in &unset {
+                enc.add_bulk(field);
+            }
+        }
+        let commands = usize::from(!set.is_empty()) + usize::from(!unset.is_empty());
+        if commands == 0 {
+            return Ok(0);
+        }
+        let mut added = 0;
+        for (i, data) in self.execute_many(commands).await?.into_iter().enumerate() {
+            let n = usize::from_lexi_data(data)?;
+            if i == 0 && !set.is_empty() {
+                added = n;
+            }
+        }
+        Ok(added)
+    }
+
+    pub async fn hget<T: FromLexiData>(
+        &mut self,
+        key: impl Into<LexiData>,
+        field: impl Into<LexiData>,
+    ) -> anyhow::Result<Option<T>> {
+        self.encoder()
+            .add_arr(3)
+            .add_bulk("HGET")
+            .add_impl_lexi_data(key)
+            .add_impl_lexi_data(field);
+        let data = self.execute().await?;
+        Option::from_lexi_data(data)
+    }
+
+    pub async fn hgetall<T: FromLexiData>(
+        &mut self,
+        key: impl Into<LexiData>,
+    ) -> anyhow::Result<HashMap<String, T>> {
+        self.encoder()
+            .add_arr(2)
+            .add_bulk("HGETALL")
+            .add_impl_lexi_data(key);
+        let arr = match self.execute().await? {
+            LexiData::Array(arr) if arr.len() % 2 == 0 => arr,
+            other => return Err(unexpected("field value pairs", other)),
+        };
+        let mut res = HashMap::with_capacity(arr.len() / 2);
+        let mut iter = arr.into_iter();
+        while let (Some(field), Some(value)) = (iter.next(), iter.next()) {
+            res.insert(String::from_lexi_data(field)?, T::from_lexi_data(value)?);
+        }
+        Ok(res)
+    }
+
+    /// Reads the hash at `key` back into a serde struct by field name, with
+    /// numbers and bools parsed from their stored form. Returns `None` if
+    /// the hash does not exist.
+    pub async fn hgetall_struct<T: DeserializeOwned>(
+        &mut self,
+        key: impl Into<LexiData>,
+    ) -> anyhow::Result<Option<T>> {
+        self.encoder()
+            .add_arr(2)
+            .add_bulk("HGETALL")
+            .add_impl_lexi_data(key);
+        match self.execute().await? {
+            LexiData::Array(arr) if arr.is_empty() => Ok(None),
+            data => Ok(Some(from_fields(data)?)),
+        }
+    }
+
+    /// Removes `fields` from the hash at `key`, returning how many existed.
+    pub async fn hdel<F>(&mut self, key: impl Into<LexiData>, fields: &[F]) -> anyhow::Result<usize>
+    where
+        F: Into<LexiData> + Clone,
+    {
+        self.encode_key_with("HDEL", key, fields);
+        let data = self.execute().await?;
+        usize::from_lexi_data(data)
+    }
+
+    pub async fn hkeys(&mut self, key: impl Into<LexiData>) -> anyhow::Result<Vec<String>> {
+        self.encoder()
+            .add_arr(2)
+            .add_bulk("HKEYS")
+            .add_impl_lexi_data(key);
+        let data = self.execute().await?;
+        Vec::from_lexi_data(data)
+    }
+
+    pub async fn hlen(&mut self, key: impl Into<LexiData>) -> anyhow::Result<usize> {
+        self.encoder()
+            .add_arr(2)
+            .add_bulk("HLEN")
+            .add_impl_lexi_data(key);
+        let data = self.execute().await?;
+        usize::from_lexi_data(data)
+    }
+
+    /// Atomically adds `by` to the integer in `field`, treating a missing
+    /// field as 0, and returns the new value.
+    pub async fn hincrby(
+        &mut self,
+        key: impl Into<LexiData>,
+        field: impl Into<LexiData>,
+        by: i64,
+    ) -> anyhow::Result<i64> {
+        self.encoder()
+            .add_arr(4)
+            .add_bulk("HINCRBY")
+            .add_impl_lexi_data(key)
+            .add_impl_lexi_data(field)
+            .add_int(by);
+        let data = self.execute().await?;
+        i64::from_lexi_data(data)
+    }
+
+    /// Watches `keys`, making the next `exec` return `None` without running
+    /// anything if one of them changes first.
+    pub async fn watch<K>(&mut self, keys: &[K]) -> anyhow::Result<()>
+    where
+        K: Into<LexiData> + Clone,
+    {
+        self.encode_keys("WATCH", keys);
+        self.execute().await?.into_result().map(|_| ())
+    }
+
+    pub async fn unwatch(&mut self) -> anyhow::Result<()> {
+        self.encoder().add_arr(1).add_bulk("UNWATCH");
+        self.execute().await?.into_result().map(|_| ())
+    }
+
+    /// Runs `tx` atomically between `MULTI` and `EXEC`.
+    ///
+    /// The commands are only sent once the server has accepted `MULTI`, so
+    /// they never run outside the transaction. If the server refuses to
+    /// queue any command the transaction is sent a `DISCARD` and the
+    /// refusal returned. Returns `None` when a watched key changed, in which
+    /// case nothing ran.
+    pub async fn exec(&mut self, tx: Transaction) -> anyhow::Result<Option<TxResults>> {
+        if let Some(e) = tx.error {
+            return Err(e);
+        }
+        // queued commands may write any key
+        if let Some(cache) = &mut self.cache {
+            cache.clear();
+        }
+        self.encoder().add_arr(1).add_bulk("MULTI");
+        self.execute().await?.into_result()?;
+        self.write_buf.clear();
+        self.write_buf.extend_from_slice(&tx.buf);
+        let refused = self
+            .execute_many(tx.len)
+            .await?
+            .into_iter()
+            .find_map(|data| match data {
+                LexiData::Error(e) => Some(e),
+                _ => None,
+            });
+        if let Some(e) = refused {
+            self.encoder().add_arr(1).add_bulk("DISCARD");
+            self.execute().await?;
+            return Err(Error::Server(e).into());
+        }
+        self.encoder().add_arr(1).add_bulk("EXEC");
+        match self.execute().await? {
+            LexiData::Array(replies) => Ok(Some(TxResults::new(replies))),
+            LexiData::Simple(SimpleString::None) => Ok(None),
+            other => Err(unexpected("transaction replies", other)),
+        }
+    }
+
+    /// Optimistically updates `keys`: watches them, then calls `build`,
+    /// which reads what it needs through the client and returns the result
+    /// of its own `exec`. `None` means a watched key changed, so the keys
+    /// are watched again and `build` retried, up to `MAX_TX_ATTEMPTS` times
+    /// before failing with `Error::TransactionConflict`.
+    ///
+    /// ```ignore
+    /// let balance = client
+    ///     .transaction(&["balance"], |client| {
+    ///         Box::pin(async move {
+    ///             let balance: i64 = client.get("balance").await?;
+    ///             let mut tx = Transaction::new();
+    ///             tx.set("balance", balance * 2);
+    ///             Ok(client.exec(tx).await?.map(|_| balance * 2))
+    ///         })
+    ///     })
+    ///     .await?;
+    /// ```
+    pub async fn transaction<K, T, F>(&mut self, keys: &[K], mut build: F) -> anyhow::Result<T>
+    where
+        K: Into<LexiData> + Clone,
+        F: for<'c> FnMut(&'c mut Client) -> BoxFuture<'c, anyhow::Result<Option<T>>>,
+    {
+        for _ in 0..MAX_TX_ATTEMPTS {
+            self.watch(keys).await?;
+            match build(self).await {
+                Ok(Some(res)) => return Ok(res),
+                Ok(None) => {}
+                Err(e) => {
+                    // build may have bailed out before its exec
+                    let _ = self.unwatch().await;
+                    return Err(e);
                 }
+            }
+        }
+        self.unwatch().await?;
+        Err(Error::TransactionConflict.into())
+    }
+
+    /// Runs a server side script with `keys` and `args`. Scripts that have
+    /// already run on this client go by their SHA1 through `evalsha` instead
+    /// of resending the source. The client keeps the source of every such
+    /// script, so clients sending many distinct scripts should call
+    /// `forget_scripts` now and then.
+    pub async fn eval<T, K, A>(&mut self, script: &str, keys: &[K], args: &[A]) -> anyhow::Result<T>
+    where
+        T: FromLexiData,
+        K: Into<LexiData> + Clone,
+        A: Into<LexiData> + Clone,
+    {
+        let sha = script_sha(script);
+        if self.scripts.contains_key(&sha) {
+            return self.evalsha(&sha, keys, args).await;
+        }
+        self.encode_script("EVAL", script, keys, args);
+        let data = self.execute().await?;
+        if !data.is_error() {
+            self.scripts.insert(sha, script.to_owned());
+        }
+        T::from_lexi_data(data)
+    }
+
+    /// Runs the script with this SHA1. If the server no longer has it but
+    /// this client has sent its source before, it is resent with `EVAL`.
+    pub async fn evalsha<T, K, A>(&mut self, sha: &str, keys: &[K], args: &[A]) -> anyhow::Result<T>
+    where
+        T: FromLexiData,
+        K: Into<LexiData> + Clone,
+        A: Into<LexiData> + Clone,
+    {
+        self.encode_script("EVALSHA", sha, keys, args);
+        let data = match self.execute().await? {
+            LexiData::Error(e) if is_missing_script(&e) => match self.scripts.get(sha) {
+                Some(script) => {
+                    let script = script.clone();
+                    self.encode_script("EVAL", &script, keys, args);
+                    self.execute().await?
+                }
+                None => LexiData::Error(e),
             },
-            None => return Err(anyhow::anyhow!("not connnected")),
+            data => data,
+        };
+        T::from_lexi_data(data)
+    }
+
+    /// Caches a script on the server without running it, returning its
+    /// SHA1 for `evalsha`.
+    pub async fn script_load(&mut self, script: &str) -> anyhow::Result<String> {
+        self.encoder()
+            .add_arr(3)
+            .add_bulk("SCRIPT")
+            .add_bulk("LOAD")
+            .add_bulk(script);
+        let sha = String::from_lexi_data(self.execute().await?)?;
+        self.scripts.insert(sha.clone(), script.to_owned());
+        Ok(sha)
+    }
+
+    /// Drops the script sources kept for `eval` and `evalsha`. Scripts the
+    /// server still caches keep running by SHA1 with `evalsha`.
+    pub fn forget_scripts(&mut self) {
+        self.scripts.clear();
+    }
+
+    /// Sends `payload` to everyone subscribed to `channel`, returning how
+    /// many subscribers received it.
+    pub async fn publish(
+        &mut self,
+        channel: impl Into<LexiData>,
+        payload: impl TryIntoLexiData,
+    ) -> anyhow::Result<usize> {
+        let payload = payload.try_into_lexi_data()?;
+        self.encoder()
+            .add_arr(3)
+            .add_bulk("PUBLISH")
+            .add_impl_lexi_data(channel)
+            .add_impl_lexi_data(payload);
+        let data = self.execute().await?;
+        usize::from_lexi_data(data)
+    }
+
+    /// Turns this connection into a subscriber. A subscribed connection can
+    /// only (un)subscribe and receive messages, so publish from another
+    /// `Client`.
+    pub fn into_pubsub(self) -> PubSub {
+        PubSub::new(self)
+    }
+
+    pub(crate) fn encoder(&mut self) -> Encoder<'_, BytesMut> {
+        self.write_buf.clear();
+        Encoder::new(&mut self.write_buf)
+    }
+
+    /// Blocks server side with `BPOP`, `BDEQUE` or `BQPOP`. Servers without
+    /// them are polled instead, backing off from 1ms up to
+    /// `MAX_POLL_DELAY` between attempts until `timeout` runs out. A zero
+    /// `timeout` pops once without blocking, as the server would take 0 to
+    /// mean waiting forever.
+    async fn blocking_pop<T: FromLexiData>(
+        &mut self,
+        pop: &Pop,
+        timeout: Duration,
+    ) -> anyhow::Result<Option<T>> {
+        if *self.native_blocking(pop) && !timeout.is_zero() {
+            // keep short timeouts from rounding down to 0
+            let ms = match millis(timeout)? {
+                0 => 1,
+                ms => ms,
+            };
+            let mut enc = self.encoder();
+            match pop {
+                Pop::Stack => enc.add_arr(2).add_bulk("BPOP"),
+                Pop::Queue => enc.add_arr(2).add_bulk("BDEQUE"),
+                Pop::Named(queue) => enc.add_arr(3).add_bulk("BQPOP").add_data(queue),
+            }
+            .add_int(ms);
+            match self.execute().await? {
+                LexiData::Error(e) if is_unknown_command(&e) => *self.native_blocking(pop) = false,
+                data => return Option::from_lexi_data(data),
+            }
+        }
+        let deadline = Instant::now() + timeout;
+        let mut delay = Duration::from_millis(1);
+        loop {
+            let mut enc = self.encoder();
+            match pop {
+                Pop::Stack => enc.add_bulk("POP"),
+                Pop::Queue => enc.add_bulk("DEQUE"),
+                Pop::Named(queue) => enc.add_arr(2).add_bulk("QPOP").add_data(queue),
+            };
+            match self.execute().await? {
+                LexiData::Simple(SimpleString::None) => {}
+                data => return T::from_lexi_data(data).map(Some),
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            tokio::time::sleep(delay.min(deadline - now)).await;
+            delay = (delay * 2).min(MAX_POLL_DELAY);
+        }
+    }
+
+    /// whether the server may know the blocking form of `pop`
+    fn native_blocking(&mut self, pop: &Pop) -> &mut bool {
+        match pop {
+            Pop::Stack => &mut self.native_bpop,
+            Pop::Queue => &mut self.native_bdeque,
+            Pop::Named(_) => &mut self.native_bqpop,
         }
+    }
+
+    fn pop_stream<'a, T: FromLexiData + 'a>(
+        &'a mut self,
+        pop: Pop,
+    ) -> impl Stream<Item = anyhow::Result<T>> + 'a {
+        stream::unfold(Some((self, pop)), |state| async move {
+            let (client, pop) = state?;
+            loop {
+                match client.blocking_pop(&pop, STREAM_WAIT).await {
+                    Ok(Some(item)) => return Some((Ok(item), Some((client, pop)))),
+                    Ok(None) => continue,
+                    Err(e) => return Some((Err(e), None)),
+                }
+            }
+        })
+    }
+
+    fn encode_set_with(&mut self, key: impl Into<LexiData>, value: LexiData, flag: &str) {
+        let key = self.invalidate(key);
+        self.encoder()
+            .add_arr(4)
+            .add_bulk("SET")
+            .add_impl_lexi_data(key)
+            .add_impl_lexi_data(value)
+            .add_bulk(flag);
+    }
+
+    fn encode_key_with<M: Into<LexiData> + Clone>(
+        &mut self,
+        command: &str,
+        key: impl Into<LexiData>,
+        items: &[M],
+    ) {
+        let mut enc = self.encoder();
+        enc.add_arr(2 + items.len())
+            .add_bulk(command)
+            .add_impl_lexi_data(key);
+        for item in items {
+            enc.add_impl_lexi_data(item.clone());
+        }
+    }
+
+    /// encodes `command script numkeys key... arg...`, dropping the keys
+    /// from the cache as the script may write them
+    fn encode_script<K, A>(&mut self, command: &str, script: &str, keys: &[K], args: &[A])
+    where
+        K: Into<LexiData> + Clone,
+        A: Into<LexiData> + Clone,
+    {
+        for key in keys {
+            self.invalidate(key.clone());
+        }
+        let mut enc = self.encoder();
+        enc.add_arr(3 + keys.len() + args.len())
+            .add_bulk(command)
+            .add_bulk(script)
+            .add_int(keys.len() as i64);
+        for key in keys {
+            enc.add_impl_lexi_data(key.clone());
+        }
+        for arg in args {
+            enc.add_impl_lexi_data(arg.clone());
+        }
+    }
+
+    fn encode_keys<K: Into<LexiData> + Clone>(&mut self, command: &str, keys: &[K]) {
+        let mut enc = self.encoder();
+        enc.add_arr(1 + keys.len()).add_bulk(command);
+        for key in keys {
+            enc.add_impl_lexi_data(key.clone());
+        }
+    }
+
+    async fn execute(&mut self) -> anyhow::Result<LexiData> {
+        self.discard_unread().await?;
+        self.exchange().await
+    }
+
+    /// sends the write buffer and reads one reply, noting that a reply is
+    /// owed until it has been read
+    async fn exchange(&mut self) -> anyhow::Result<LexiData> {
+        self.in_flight = true;
+        self.send().await?;
+        let data = self.read_frame().await?;
+        self.in_flight = false;
+        Ok(data)
+    }
+
+    /// sends every command in the write buffer at once, then reads the
+    /// `count` responses they produce
+    async fn execute_many(&mut self, count: usize) -> anyhow::Result<Vec<LexiData>> {
+        self.discard_unread().await?;
+        self.in_flight = true;
+        self.send().await?;
+        let mut responses = Vec::with_capacity(count);
+        for _ in 0..count {
+            responses.push(self.read_frame().await?);
+        }
+        self.in_flight = false;
+        Ok(responses)
+    }
+
+    /// reconnects if an earlier call was dropped before reading its reply,
+    /// which would otherwise be read as the reply to the next command. A
+    /// blocking pop still waiting on the server ends with the connection
+    /// rather than popping an element nobody reads.
+    async fn discard_unread(&mut self) -> anyhow::Result<()> {
+        if !self.in_flight || self.stream.is_none() {
+            return Ok(());
+        }
+        let pending = std::mem::take(&mut self.write_buf);
+        self.connect().await?;
+        self.write_buf = pending;
         Ok(())
     }
 
-    async fn read(&mut self) -> anyhow::Result<Vec<u8>> {
-        let mut buf = Vec::with_capacity(4096);
-        match &self.stream {
-            Some(stream) => loop {
-                stream.readable().await?;
-                match stream.try_read_buf(&mut buf) {
-                    Ok(_) => break,
-                    Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => continue,
-                    Err(e) => return Err(e.into()),
+    pub(crate) async fn send(&mut self) -> anyhow::Result<()> {
+        let stream = self.stream.as_mut().ok_or(Error::NotConnected)?;
+        if let Err(e) = stream.write_all(&self.write_buf).await {
+            self.disconnect();
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// reads the next reply, handling push frames the server sent on its own
+    async fn read_frame(&mut self) -> anyhow::Result<LexiData> {
+        loop {
+            match self.read_any_frame().await? {
+                LexiData::Push(push) => self.handle_push(&push),
+                data => return Ok(data),
+            }
+        }
+    }
+
+    /// handles push frames that arrived while no command was waiting,
+    /// without blocking
+    fn read_pushes(&mut self) -> anyhow::Result<()> {
+        if let Some(stream) = &self.stream {
+            loop {
+                match stream.try_read_buf(&mut self.read_buf) {
+                    Ok(0) => {
+                        self.disconnect();
+                        break;
+                    }
+                    Ok(_) => {}
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(e) => {
+                        self.disconnect();
+                        return Err(e.into());
+                    }
                 }
-            },
-            None => unreachable!(),
+            }
         }
-        Ok(buf)
+        while let Some(data) = self.parse_buffered()? {
+            match data {
+                LexiData::Push(push) => self.handle_push(&push),
+                other => {
+                    self.disconnect();
+                    return Err(unexpected("push frame", other));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_push(&mut self, push: &[LexiData]) {
+        let Some(cache) = &mut self.cache else {
+            return;
+        };
+        let Some(keys) = invalidated_keys(push) else {
+            cache.clear();
+            return;
+        };
+        for key in keys {
+            // the server names every key as a string, so drop the int key
+            // that string may have been sent as too
+            if let Some(int_key) = key.parse().ok().map(LexiData::Int) {
+                cache.invalidate(&cache_key(&int_key).expect("ints are cached"));
+            }
+            cache.invalidate(&cache_key(&LexiData::Bulk(key)).expect("strings are cached"));
+        }
+    }
+
+    /// turns on the server messages that keep the cache fresh, clearing
+    /// whatever was cached before a reconnect
+    async fn enable_invalidation(&mut self) -> anyhow::Result<()> {
+        if let Some(cache) = &mut self.cache {
+            cache.clear();
+        }
+        self.encoder()
+            .add_arr(3)
+            .add_bulk("CLIENT")
+            .add_bulk("TRACKING")
+            .add_bulk("ON");
+        match self.exchange().await? {
+            LexiData::Error(e) if is_unknown_command(&e) => Err(anyhow::anyhow!(
+                "client side caching needs CLIENT TRACKING, which the server does not support: {}",
+                e
+            )),
+            data => data.into_result().map(|_| ()),
+        }
+    }
+
+    /// reads until a whole frame is buffered, keeping any bytes after it
+    /// for the next call
+    pub(crate) async fn read_any_frame(&mut self) -> anyhow::Result<LexiData> {
+        loop {
+            if let Some(data) = self.parse_buffered()? {
+                return Ok(data);
+            }
+            let stream = self.stream.as_mut().ok_or(Error::NotConnected)?;
+            match stream.read_buf(&mut self.read_buf).await {
+                Ok(0) => {
+                    self.disconnect();
+                    return Err(Error::ConnectionClosed.into());
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    self.disconnect();
+                    return Err(e.into());
+                }
+            }
+        }
+    }
+
+    /// takes the first whole frame out of the read buffer. Bytes that do
+    /// not parse leave no way to find where the next frame starts, so the
+    /// connection is dropped rather than failing on them forever.
+    fn parse_buffered(&mut self) -> anyhow::Result<Option<LexiData>> {
+        match Parser::parse_frame(&self.read_buf) {
+            Ok(Some((data, len))) => {
+                self.read_buf.advance(len);
+                Ok(Some(data))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => {
+                self.disconnect();
+                Err(e)
+            }
+        }
+    }
+
+    /// drops `key` from the cache ahead of a command that writes it, handing
+    /// it back for encoding
+    fn invalidate(&mut self, key: impl Into<LexiData>) -> LexiData {
+        let key = key.into();
+        if let (Some(cache), Some(cache_key)) = (&mut self.cache, cache_key(&key)) {
+            cache.invalidate(&cache_key);
+        }
+        key
+    }
+
+    /// drops the connection, and with it the cache, as invalidations can
+    /// not arrive until the next `connect`
+    fn disconnect(&mut self) {
+        self.stream = None;
+        self.read_buf.clear();
+        self.in_flight = false;
+        if let Some(cache) = &mut self.cache {
+            cache.clear();
+        }
+    }
+}
+
+/// the name a `GET` of `key` is cached under, for keys that are cached.
+/// It starts with the key's type marker, so `5` and `"5"` stay apart.
+fn cache_key(key: &LexiData) -> Option<String> {
+    match key {
+        LexiData::Bulk(s) => Some(format!("${}", s)),
+        LexiData::Int(i) => Some(format!(":{}", i)),
+        _ => None,
+    }
+}
+
+/// the longest a client side blocking pop sleeps between polls
+const MAX_POLL_DELAY: Duration = Duration::from_millis(100);
+
+/// how many times `Client::transaction` runs before giving up
+pub const MAX_TX_ATTEMPTS: usize = 16;
+
+/// how long each wait in a pop stream blocks before asking again
+const STREAM_WAIT: Duration = Duration::from_secs(5);
+
+/// where a blocking pop takes its element from
+enum Pop {
+    Stack,
+    Queue,
+    Named(LexiData),
+}
+
+/// decodes a `member, score, member, score, ...` reply
+fn scored_members(data: LexiData) -> anyhow::Result<Vec<(String, f64)>> {
+    let arr = match data {
+        LexiData::Array(arr) if arr.len() % 2 == 0 => arr,
+        other => return Err(unexpected("member and score pairs", other)),
+    };
+    let mut res = Vec::with_capacity(arr.len() / 2);
+    let mut iter = arr.into_iter();
+    while let (Some(member), Some(score)) = (iter.next(), iter.next()) {
+        res.push((String::from_lexi_data(member)?, f64::from_lexi_data(score)?));
+    }
+    Ok(res)
+}
+
+fn millis(d: Duration) -> anyhow::Result<i64> {
+    Ok(i64::try_from(d.as_millis())?)
+}
+
+/// the lowercase hex SHA1 a server caches a script under
+fn script_sha(script: &str) -> String {
+    sha1_smol::Sha1::from(script).digest().to_string()
+}
+
+fn is_missing_script(e: &str) -> bool {
+    e.starts_with("NOSCRIPT") || e.to_ascii_lowercase().contains("no matching script")
+}
+
+fn is_unknown_command(e: &str) -> bool {
+    let e = e.to_ascii_lowercase();
+    e.contains("unknown command") || e.contains("invalid command")
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::{HashMap, HashSet},
+        pin::pin,
+        time::Duration,
+    };
+
+    use futures::{StreamExt, TryStreamExt};
+    use serde::{Deserialize, Serialize};
+
+    use super::{Client, ClientConfig, Ttl};
+    use crate::{
+        error::Error,
+        lexi_data::{LexiData, SimpleString},
+        stub::Stub,
+        transaction::Transaction,
+    };
+
+    const AUTH: &[u8] = b"*3\r\n$4\r\nAUTH\r\n$5\r\nvince\r\n$6\r\nsecret\r\n";
+
+    #[tokio::test]
+    async fn it_authenticates_on_connect() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![(AUTH, b"+OK\r\n"), (b"+PING\r\n", b"+PONG\r\n")]).await?;
+        let config = ClientConfig::new(stub.addr())?.credentials("vince", "secret");
+        let mut client = Client::with_config(config);
+        client.connect().await?;
+        assert_eq!(client.ping().await?, LexiData::Simple(SimpleString::Pong));
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_reports_rejected_credentials() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![(AUTH, b"-bad password\r\n")]).await?;
+        let config = ClientConfig::new(stub.addr())?.credentials("vince", "secret");
+        let mut client = Client::with_config(config);
+        let err = client.connect().await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::AuthFailed("bad password".to_owned()))
+        );
+        let err = client.ping().await.unwrap_err();
+        assert_eq!(err.downcast_ref::<Error>(), Some(&Error::NotConnected));
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_disconnects_on_replies_that_do_not_parse() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![(b"+PING\r\n", b"?bad\r\n")]).await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        assert!(client.ping().await.is_err());
+        assert!(!client.is_connected());
+        let err = client.ping().await.unwrap_err();
+        assert_eq!(err.downcast_ref::<Error>(), Some(&Error::NotConnected));
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_skips_auth_without_credentials() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![(b"+PING\r\n", b"+PONG\r\n")]).await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        assert_eq!(client.ping().await?, LexiData::Simple(SimpleString::Pong));
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_filters_keys_by_pattern() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![(
+            b"*2\r\n$4\r\nKEYS\r\n$6\r\nuser:*\r\n",
+            b"*2\r\n$6\r\nuser:1\r\n$6\r\nuser:2\r\n",
+        )])
+        .await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        assert_eq!(
+            client.keys_matching("user:*").await?,
+            vec!["user:1", "user:2"]
+        );
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_scans_keys_in_pages() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![
+            (
+                b"*6\r\n$4\r\nSCAN\r\n$1\r\n0\r\n$5\r\nMATCH\r\n$1\r\n*\r\n$5\r\nCOUNT\r\n$1\r\n2\r\n",
+                b"*2\r\n:7\r\n*2\r\n$1\r\na\r\n$1\r\nb\r\n",
+            ),
+            (
+                b"*6\r\n$4\r\nSCAN\r\n$1\r\n7\r\n$5\r\nMATCH\r\n$1\r\n*\r\n$5\r\nCOUNT\r\n$1\r\n2\r\n",
+                b"*2\r\n$1\r\n0\r\n*1\r\n$1\r\nc\r\n",
+            ),
+        ])
+        .await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        let keys: Vec<String> = client.scan("*", 2).try_collect().await?;
+        assert_eq!(keys, vec!["a", "b", "c"]);
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_reads_responses_spanning_many_reads() -> anyhow::Result<()> {
+        let value = "x".repeat(256 * 1024);
+        let reply = format!("${}\r\n{}\r\n", value.len(), value).into_bytes();
+        let reply: &'static [u8] = Box::leak(reply.into_boxed_slice());
+        let stub = Stub::serve(vec![
+            (b"*2\r\n$3\r\nGET\r\n$3\r\nbig\r\n", reply),
+            (b"+PING\r\n", b"+PONG\r\n"),
+        ])
+        .await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        let got: String = client.get("big").await?;
+        assert_eq!(got, value);
+        assert_eq!(client.ping().await?, LexiData::Simple(SimpleString::Pong));
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_batches_multi_key_commands() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![
+            (
+                b"*5\r\n$4\r\nMSET\r\n$1\r\na\r\n:1\r\n$1\r\nb\r\n:2\r\n",
+                b"+OK\r\n",
+            ),
+            (
+                b"*4\r\n$4\r\nMGET\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\nc\r\n",
+                b"*3\r\n:1\r\n:2\r\n+NONE\r\n",
+            ),
+            (b"*3\r\n$4\r\nMDEL\r\n$1\r\na\r\n$1\r\nc\r\n", b":1\r\n"),
+        ])
+        .await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        client.mset(&[("a", 1), ("b", 2)]).await?;
+        let values: Vec<Option<i64>> = client.mget(&["a", "b", "c"]).await?;
+        assert_eq!(values, vec![Some(1), Some(2), None]);
+        assert_eq!(client.mdel(&["a", "c"]).await?, 1);
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_pipelines_multi_key_commands_without_server_support() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![
+            (
+                b"*3\r\n$4\r\nMGET\r\n$1\r\na\r\n$1\r\nb\r\n",
+                b"-unknown command\r\n",
+            ),
+            (
+                b"*2\r\n$3\r\nGET\r\n$1\r\na\r\n*2\r\n$3\r\nGET\r\n$1\r\nb\r\n",
+                b"$1\r\nx\r\n+NONE\r\n",
+            ),
+            (
+                b"*3\r\n$4\r\nMDEL\r\n$1\r\na\r\n$1\r\nb\r\n",
+                b"-unknown command\r\n",
+            ),
+            (
+                b"*2\r\n$3\r\nDEL\r\n$1\r\na\r\n*2\r\n$3\r\nDEL\r\n$1\r\nb\r\n",
+                b"+OK\r\n+NONE\r\n",
+            ),
+        ])
+        .await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        let values: Vec<Option<String>> = client.mget(&["a", "b"]).await?;
+        assert_eq!(values, vec![Some("x".to_owned()), None]);
+        assert_eq!(client.mdel(&["a", "b"]).await?, 1);
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_manages_expiry() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![
+            (
+                b"*5\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\nb\r\n$2\r\nPX\r\n:1500\r\n",
+                b"+OK\r\n",
+            ),
+            (b"*3\r\n$7\r\nPEXPIRE\r\n$1\r\nz\r\n:60000\r\n", b":0\r\n"),
+            (b"*2\r\n$4\r\nPTTL\r\n$1\r\na\r\n", b":1499\r\n"),
+            (b"*2\r\n$7\r\nPERSIST\r\n$1\r\na\r\n", b":1\r\n"),
+            (b"*2\r\n$4\r\nPTTL\r\n$1\r\na\r\n", b":-1\r\n"),
+            (b"*2\r\n$4\r\nPTTL\r\n$1\r\nz\r\n", b":-2\r\n"),
+        ])
+        .await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        client.set_ex("a", "b", Duration::from_millis(1500)).await?;
+        assert!(!client.expire("z", Duration::from_secs(60)).await?);
+        assert_eq!(
+            client.ttl("a").await?,
+            Ttl::Expires(Duration::from_millis(1499))
+        );
+        assert!(client.persist("a").await?);
+        assert_eq!(client.ttl("a").await?, Ttl::Persistent);
+        assert_eq!(client.ttl("z").await?, Ttl::Missing);
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_updates_counters() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![
+            (b"*2\r\n$4\r\nINCR\r\n$1\r\nn\r\n", b":1\r\n"),
+            (b"*3\r\n$6\r\nINCRBY\r\n$1\r\nn\r\n:10\r\n", b":11\r\n"),
+            (b"*3\r\n$6\r\nDECRBY\r\n$1\r\nn\r\n:3\r\n", b":8\r\n"),
+            (b"*2\r\n$4\r\nDECR\r\n$1\r\nn\r\n", b":7\r\n"),
+            (
+                b"*3\r\n$11\r\nINCRBYFLOAT\r\n$1\r\nf\r\n,0.5\r\n",
+                b",2.5\r\n",
+            ),
+            (
+                b"*2\r\n$4\r\nINCR\r\n$1\r\ns\r\n",
+                b"-value is not an integer\r\n",
+            ),
+        ])
+        .await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        assert_eq!(client.incr("n").await?, 1);
+        assert_eq!(client.incrby("n", 10).await?, 11);
+        assert_eq!(client.decrby("n", 3).await?, 8);
+        assert_eq!(client.decr("n").await?, 7);
+        assert_eq!(client.incrbyfloat("f", 0.5).await?, 2.5);
+        let err = client.incr("s").await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::Server("value is not an integer".to_owned()))
+        );
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_sets_conditionally() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![
+            (
+                b"*4\r\n$3\r\nSET\r\n$4\r\nlock\r\n$1\r\na\r\n$2\r\nNX\r\n",
+                b"+OK\r\n",
+            ),
+            (
+                b"*4\r\n$3\r\nSET\r\n$4\r\nlock\r\n$1\r\nb\r\n$2\r\nNX\r\n",
+                b"+NONE\r\n",
+            ),
+            (
+                b"*4\r\n$3\r\nSET\r\n$4\r\nnope\r\n$1\r\nb\r\n$2\r\nXX\r\n",
+                b"+NONE\r\n",
+            ),
+            (
+                b"*4\r\n$3\r\nSET\r\n$4\r\nlock\r\n$1\r\nb\r\n$3\r\nGET\r\n",
+                b"$1\r\na\r\n",
+            ),
+            (
+                b"*4\r\n$3\r\nSET\r\n$4\r\nnope\r\n$1\r\nb\r\n$3\r\nGET\r\n",
+                b"+NONE\r\n",
+            ),
+        ])
+        .await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        assert!(client.set_nx("lock", "a").await?);
+        assert!(!client.set_nx("lock", "b").await?);
+        assert!(!client.set_xx("nope", "b").await?);
+        let old: Option<String> = client.set_get("lock", "b").await?;
+        assert_eq!(old.as_deref(), Some("a"));
+        let old: Option<String> = client.set_get("nope", "b").await?;
+        assert_eq!(old, None);
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_uses_named_lists() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![
+            (b"*3\r\n$5\r\nLPUSH\r\n$4\r\njobs\r\n$1\r\na\r\n", b":1\r\n"),
+            (b"*3\r\n$5\r\nRPUSH\r\n$4\r\njobs\r\n$1\r\nb\r\n", b":2\r\n"),
+            (b"*2\r\n$4\r\nLLEN\r\n$4\r\njobs\r\n", b":2\r\n"),
+            (
+                b"*4\r\n$6\r\nLRANGE\r\n$4\r\njobs\r\n:0\r\n:-1\r\n",
+                b"*2\r\n$1\r\na\r\n$1\r\nb\r\n",
+            ),
+            (
+                b"*3\r\n$6\r\nLINDEX\r\n$4\r\njobs\r\n:-1\r\n",
+                b"$1\r\nb\r\n",
+            ),
+            (b"*2\r\n$4\r\nRPOP\r\n$4\r\njobs\r\n", b"$1\r\nb\r\n"),
+            (b"*2\r\n$4\r\nLPOP\r\n$4\r\njobs\r\n", b"$1\r\na\r\n"),
+            (b"*2\r\n$4\r\nLPOP\r\n$4\r\njobs\r\n", b"+NONE\r\n"),
+        ])
+        .await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        assert_eq!(client.lpush("jobs", "a").await?, 1);
+        assert_eq!(client.rpush("jobs", "b").await?, 2);
+        assert_eq!(client.llen("jobs").await?, 2);
+        let all: Vec<String> = client.lrange("jobs", 0, -1).await?;
+        assert_eq!(all, vec!["a", "b"]);
+        let last: Option<String> = client.lindex("jobs", -1).await?;
+        assert_eq!(last.as_deref(), Some("b"));
+        assert_eq!(client.rpop::<String>("jobs").await?.as_deref(), Some("b"));
+        assert_eq!(client.lpop::<String>("jobs").await?.as_deref(), Some("a"));
+        assert_eq!(client.lpop::<String>("jobs").await?, None);
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_uses_named_queues() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![
+            (b"*3\r\n$5\r\nQPUSH\r\n$1\r\nq\r\n:7\r\n", b":1\r\n"),
+            (b"*2\r\n$5\r\nQPEEK\r\n$1\r\nq\r\n", b":7\r\n"),
+            (b"*2\r\n$4\r\nQLEN\r\n$1\r\nq\r\n", b":1\r\n"),
+            (
+                b"*4\r\n$6\r\nQRANGE\r\n$1\r\nq\r\n:0\r\n:9\r\n",
+                b"*1\r\n:7\r\n",
+            ),
+            (b"*2\r\n$4\r\nQPOP\r\n$1\r\nq\r\n", b":7\r\n"),
+        ])
+        .await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        assert_eq!(client.qpush("q", 7).await?, 1);
+        assert_eq!(client.qpeek::<i64>("q").await?, Some(7));
+        assert_eq!(client.qlen("q").await?, 1);
+        assert_eq!(client.qrange::<i64>("q", 0, 9).await?, vec![7]);
+        assert_eq!(client.qpop::<i64>("q").await?, Some(7));
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_blocks_server_side() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![
+            (b"*2\r\n$6\r\nBDEQUE\r\n:250\r\n", b"+NONE\r\n"),
+            (b"*2\r\n$4\r\nBPOP\r\n:250\r\n", b"$1\r\na\r\n"),
+            (b"*2\r\n$4\r\nBPOP\r\n:1\r\n", b"+NONE\r\n"),
+            (b"$5\r\nDEQUE\r\n", b"$1\r\nb\r\n"),
+        ])
+        .await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        let timeout = Duration::from_millis(250);
+        assert_eq!(client.bdeque::<String>(timeout).await?, None);
+        assert_eq!(client.bpop::<String>(timeout).await?.as_deref(), Some("a"));
+        let timeout = Duration::from_micros(500);
+        assert_eq!(client.bpop::<String>(timeout).await?, None);
+        let item = client.bdeque::<String>(Duration::ZERO).await?;
+        assert_eq!(item.as_deref(), Some("b"));
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_polls_without_server_support() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![
+            (
+                b"*3\r\n$5\r\nBQPOP\r\n$1\r\nq\r\n:1000\r\n",
+                b"-invalid command\r\n",
+            ),
+            (b"*2\r\n$4\r\nQPOP\r\n$1\r\nq\r\n", b"+NONE\r\n"),
+            (b"*2\r\n$4\r\nQPOP\r\n$1\r\nq\r\n", b"+NONE\r\n"),
+            (b"*2\r\n$4\r\nQPOP\r\n$1\r\nq\r\n", b":5\r\n"),
+            (b"$5\r\nDEQUE\r\n", b"+NONE\r\n"),
+        ])
+        .await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        let item = client.bqpop::<i64>("q", Duration::from_secs(1)).await?;
+        assert_eq!(item, Some(5));
+        let item = client.bdeque::<i64>(Duration::ZERO).await?;
+        assert_eq!(item, None);
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_streams_dequeued_items() -> anyhow::Result<()> {
+        let bqpop: &[u8] = b"*3\r\n$5\r\nBQPOP\r\n$4\r\njobs\r\n:5000\r\n";
+        let stub = Stub::serve(vec![
+            (bqpop, b"$1\r\na\r\n"),
+            (bqpop, b"+NONE\r\n"),
+            (bqpop, b"$1\r\nb\r\n"),
+        ])
+        .await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        let items: Vec<String> = client.qpop_stream("jobs").take(2).try_collect().await?;
+        assert_eq!(items, vec!["a", "b"]);
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_reconnects_after_a_dropped_stream() -> anyhow::Result<()> {
+        let stub = Stub::bind().await?.play_each(vec![
+            vec![(b"*3\r\n$5\r\nBQPOP\r\n$4\r\njobs\r\n:5000\r\n", b"")],
+            vec![(b"+PING\r\n", b"+PONG\r\n")],
+        ]);
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        {
+            let mut jobs = pin!(client.qpop_stream::<String>("jobs"));
+            let next = tokio::time::timeout(Duration::from_millis(50), jobs.next()).await;
+            assert!(next.is_err());
+        }
+        assert_eq!(client.ping().await?, LexiData::Simple(SimpleString::Pong));
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_uses_named_sets() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![
+            (
+                b"*4\r\n$4\r\nSADD\r\n$1\r\ns\r\n$1\r\na\r\n$1\r\nb\r\n",
+                b":2\r\n",
+            ),
+            (
+                b"*3\r\n$9\r\nSISMEMBER\r\n$1\r\ns\r\n$1\r\na\r\n",
+                b":1\r\n",
+            ),
+            (b"*2\r\n$5\r\nSCARD\r\n$1\r\ns\r\n", b":2\r\n"),
+            (
+                b"*2\r\n$8\r\nSMEMBERS\r\n$1\r\ns\r\n",
+                b"*2\r\n$1\r\nb\r\n$1\r\na\r\n",
+            ),
+            (b"*2\r\n$11\r\nSRANDMEMBER\r\n$1\r\ne\r\n", b"+NONE\r\n"),
+            (
+                b"*3\r\n$6\r\nSINTER\r\n$1\r\ns\r\n$1\r\nt\r\n",
+                b"*1\r\n$1\r\na\r\n",
+            ),
+            (b"*3\r\n$4\r\nSREM\r\n$1\r\ns\r\n$1\r\na\r\n", b":1\r\n"),
+        ])
+        .await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        assert_eq!(client.sadd("s", &["a", "b"]).await?, 2);
+        assert!(client.sismember("s", "a").await?);
+        assert_eq!(client.scard("s").await?, 2);
+        let members: HashSet<String> = client.smembers("s").await?;
+        assert_eq!(members, HashSet::from(["a".to_owned(), "b".to_owned()]));
+        assert_eq!(client.srandmember::<String>("e").await?, None);
+        let common: HashSet<String> = client.sinter(&["s", "t"]).await?;
+        assert_eq!(common, HashSet::from(["a".to_owned()]));
+        assert_eq!(client.srem("s", &["a"]).await?, 1);
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_uses_sorted_sets() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![
+            (
+                b"*4\r\n$4\r\nZADD\r\n$5\r\nboard\r\n,10.5\r\n$1\r\na\r\n",
+                b":1\r\n",
+            ),
+            (
+                b"*4\r\n$7\r\nZINCRBY\r\n$5\r\nboard\r\n,2.0\r\n$1\r\nb\r\n",
+                b",2.0\r\n",
+            ),
+            (
+                b"*3\r\n$5\r\nZRANK\r\n$5\r\nboard\r\n$1\r\na\r\n",
+                b":1\r\n",
+            ),
+            (
+                b"*3\r\n$6\r\nZSCORE\r\n$5\r\nboard\r\n$1\r\nz\r\n",
+                b"+NONE\r\n",
+            ),
+            (
+                b"*5\r\n$6\r\nZRANGE\r\n$5\r\nboard\r\n:0\r\n:-1\r\n$10\r\nWITHSCORES\r\n",
+                b"*4\r\n$1\r\nb\r\n,2.0\r\n$1\r\na\r\n,10.5\r\n",
+            ),
+            (
+                b"*8\r\n$13\r\nZRANGEBYSCORE\r\n$5\r\nboard\r\n,5.0\r\n,inf\r\n$10\r\nWITHSCORES\r\n$5\r\nLIMIT\r\n:0\r\n:10\r\n",
+                b"*2\r\n$1\r\na\r\n,10.5\r\n",
+            ),
+        ])
+        .await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        assert!(client.zadd("board", 10.5, "a").await?);
+        assert_eq!(client.zincrby("board", 2.0, "b").await?, 2.0);
+        assert_eq!(client.zrank("board", "a").await?, Some(1));
+        assert_eq!(client.zscore("board", "z").await?, None);
+        assert_eq!(
+            client.zrange("board", 0, -1).await?,
+            vec![("b".to_owned(), 2.0), ("a".to_owned(), 10.5)]
+        );
+        assert_eq!(
+            client
+                .zrangebyscore("board", 5.0, f64::INFINITY, Some((0, 10)))
+                .await?,
+            vec![("a".to_owned(), 10.5)]
+        );
+        stub.finish().await
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Profile {
+        name: String,
+        visits: u32,
+        nickname: Option<String>,
+    }
+
+    #[tokio::test]
+    async fn it_uses_hashes() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![
+            (
+                b"*4\r\n$4\r\nHSET\r\n$1\r\nh\r\n$1\r\na\r\n:1\r\n",
+                b":1\r\n",
+            ),
+            (
+                b"*4\r\n$7\r\nHINCRBY\r\n$1\r\nh\r\n$1\r\na\r\n:2\r\n",
+                b":3\r\n",
+            ),
+            (b"*3\r\n$4\r\nHGET\r\n$1\r\nh\r\n$1\r\nz\r\n", b"+NONE\r\n"),
+            (
+                b"*2\r\n$7\r\nHGETALL\r\n$1\r\nh\r\n",
+                b"*4\r\n$1\r\na\r\n:3\r\n$1\r\nb\r\n:4\r\n",
+            ),
+            (
+                b"*2\r\n$5\r\nHKEYS\r\n$1\r\nh\r\n",
+                b"*2\r\n$1\r\na\r\n$1\r\nb\r\n",
+            ),
+            (b"*2\r\n$4\r\nHLEN\r\n$1\r\nh\r\n", b":2\r\n"),
+            (
+                b"*4\r\n$4\r\nHDEL\r\n$1\r\nh\r\n$1\r\na\r\n$1\r\nz\r\n",
+                b":1\r\n",
+            ),
+        ])
+        .await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        assert!(client.hset("h", "a", 1).await?);
+        assert_eq!(client.hincrby("h", "a", 2).await?, 3);
+        assert_eq!(client.hget::<i64>("h", "z").await?, None);
+        let all: HashMap<String, i64> = client.hgetall("h").await?;
+        assert_eq!(
+            all,
+            HashMap::from([("a".to_owned(), 3), ("b".to_owned(), 4)])
+        );
+        assert_eq!(client.hkeys("h").await?, vec!["a", "b"]);
+        assert_eq!(client.hlen("h").await?, 2);
+        assert_eq!(client.hdel("h", &["a", "z"]).await?, 1);
+        stub.finish().await
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn it_stores_json_values() -> anyhow::Result<()> {
+        use crate::codec::Json;
+
+        let stub = Stub::serve(vec![
+            (
+                b"*3\r\n$3\r\nSET\r\n$6\r\nuser:1\r\n$43\r\n{\"name\":\"vince\",\"visits\":3,\"nickname\":null}\r\n",
+                b"+OK\r\n",
+            ),
+            (
+                b"*2\r\n$3\r\nGET\r\n$6\r\nuser:1\r\n",
+                b"$43\r\n{\"name\":\"vince\",\"visits\":3,\"nickname\":null}\r\n",
+            ),
+        ])
+        .await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        let profile = Profile {
+            name: "vince".to_owned(),
+            visits: 3,
+            nickname: None,
+        };
+        assert!(client.set("user:1", Json(&profile)).await?.is_ok());
+        let Json(back): Json<Profile> = client.get("user:1").await?;
+        assert_eq!(back, profile);
+        let unencodable = HashMap::from([((1, 2), "a")]);
+        assert!(client.set("user:2", Json(unencodable)).await.is_err());
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_maps_structs_to_hashes() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![
+            (
+                b"*6\r\n$4\r\nHSET\r\n$6\r\nuser:1\r\n$4\r\nname\r\n$5\r\nvince\r\n$6\r\nvisits\r\n:3\r\n\
+                  *3\r\n$4\r\nHDEL\r\n$6\r\nuser:1\r\n$8\r\nnickname\r\n",
+                b":1\r\n:1\r\n",
+            ),
+            (
+                b"*2\r\n$7\r\nHGETALL\r\n$6\r\nuser:1\r\n",
+                b"*4\r\n$6\r\nvisits\r\n$1\r\n3\r\n$4\r\nname\r\n$5\r\nvince\r\n",
+            ),
+            (
+                b"*2\r\n$7\r\nHGETALL\r\n$6\r\nuser:2\r\n",
+                b"*0\r\n",
+            ),
+        ])
+        .await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        let profile = Profile {
+            name: "vince".to_owned(),
+            visits: 3,
+            nickname: None,
+        };
+        assert_eq!(client.hset_struct("user:1", &profile).await?, 1);
+        assert_eq!(client.hgetall_struct("user:1").await?, Some(profile));
+        assert_eq!(client.hgetall_struct::<Profile>("user:2").await?, None);
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_executes_transactions() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![
+            (b"*1\r\n$5\r\nMULTI\r\n", b"+OK\r\n"),
+            (
+                b"*3\r\n$3\r\nSET\r\n$1\r\na\r\n:1\r\n*2\r\n$4\r\nINCR\r\n$1\r\na\r\n",
+                b"+QUEUED\r\n+QUEUED\r\n",
+            ),
+            (b"*1\r\n$4\r\nEXEC\r\n", b"*2\r\n+OK\r\n:2\r\n"),
+            (b"*1\r\n$5\r\nMULTI\r\n", b"+OK\r\n"),
+            (
+                b"*2\r\n$4\r\nNOPE\r\n$1\r\na\r\n",
+                b"-unknown command NOPE\r\n",
+            ),
+            (b"*1\r\n$7\r\nDISCARD\r\n", b"+OK\r\n"),
+            (
+                b"*1\r\n$5\r\nMULTI\r\n",
+                b"-MULTI calls can not be nested\r\n",
+            ),
+        ])
+        .await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+
+        let mut tx = Transaction::new();
+        let set = tx.set("a", 1);
+        let incr = tx.incr("a");
+        let results = client.exec(tx).await?.expect("nothing was watched");
+        results.get(set)?;
+        assert_eq!(results.get(incr)?, 2);
+
+        let mut tx = Transaction::new();
+        tx.command::<LexiData, _>("NOPE", ["a"]);
+        let err = client.exec(tx).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::Server("unknown command NOPE".to_owned()))
+        );
+
+        let mut tx = Transaction::new();
+        tx.set("a", 2);
+        let err = client.exec(tx).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::Server("MULTI calls can not be nested".to_owned()))
+        );
+
+        let mut tx = Transaction::new();
+        tx.expire("a", Duration::MAX);
+        assert!(client.exec(tx).await.is_err());
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_retries_transactions_when_watched_keys_change() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![
+            (b"*2\r\n$5\r\nWATCH\r\n$3\r\nbal\r\n", b"+OK\r\n"),
+            (b"*2\r\n$3\r\nGET\r\n$3\r\nbal\r\n", b":1\r\n"),
+            (b"*1\r\n$5\r\nMULTI\r\n", b"+OK\r\n"),
+            (b"*3\r\n$3\r\nSET\r\n$3\r\nbal\r\n:2\r\n", b"+QUEUED\r\n"),
+            (b"*1\r\n$4\r\nEXEC\r\n", b"+NONE\r\n"),
+            (b"*2\r\n$5\r\nWATCH\r\n$3\r\nbal\r\n", b"+OK\r\n"),
+            (b"*2\r\n$3\r\nGET\r\n$3\r\nbal\r\n", b":5\r\n"),
+            (b"*1\r\n$5\r\nMULTI\r\n", b"+OK\r\n"),
+            (b"*3\r\n$3\r\nSET\r\n$3\r\nbal\r\n:10\r\n", b"+QUEUED\r\n"),
+            (b"*1\r\n$4\r\nEXEC\r\n", b"*1\r\n+OK\r\n"),
+        ])
+        .await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        let doubled = client
+            .transaction(&["bal"], |client| {
+                Box::pin(async move {
+                    let bal: i64 = client.get("bal").await?;
+                    let mut tx = Transaction::new();
+                    tx.set("bal", bal * 2);
+                    Ok(client.exec(tx).await?.map(|_| bal * 2))
+                })
+            })
+            .await?;
+        assert_eq!(doubled, 10);
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_caches_scripts_by_sha() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![
+            (
+                b"*5\r\n$4\r\nEVAL\r\n$8\r\nreturn 1\r\n:1\r\n$1\r\nk\r\n:5\r\n",
+                b":1\r\n",
+            ),
+            (
+                b"*5\r\n$7\r\nEVALSHA\r\n$40\r\ne0e1f9fabfc9d4800c877a703b823ac0578ff8db\r\n:1\r\n$1\r\nk\r\n:5\r\n",
+                b":1\r\n",
+            ),
+            (
+                b"*5\r\n$7\r\nEVALSHA\r\n$40\r\ne0e1f9fabfc9d4800c877a703b823ac0578ff8db\r\n:1\r\n$1\r\nk\r\n:5\r\n",
+                b"-NOSCRIPT No matching script\r\n",
+            ),
+            (
+                b"*5\r\n$4\r\nEVAL\r\n$8\r\nreturn 1\r\n:1\r\n$1\r\nk\r\n:5\r\n",
+                b":1\r\n",
+            ),
+            (
+                b"*3\r\n$7\r\nEVALSHA\r\n$3\r\nabc\r\n:0\r\n",
+                b"-NOSCRIPT No matching script\r\n",
+            ),
+        ])
+        .await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        let no_keys: [&str; 0] = [];
+        let no_args: [i64; 0] = [];
+        assert_eq!(client.eval::<i64, _, _>("return 1", &["k"], &[5]).await?, 1);
+        assert_eq!(client.eval::<i64, _, _>("return 1", &["k"], &[5]).await?, 1);
+        let sha = "e0e1f9fabfc9d4800c877a703b823ac0578ff8db";
+        assert_eq!(client.evalsha::<i64, _, _>(sha, &["k"], &[5]).await?, 1);
+        let err = client
+            .evalsha::<i64, _, _>("abc", &no_keys, &no_args)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::Server("NOSCRIPT No matching script".to_owned()))
+        );
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_only_keeps_scripts_that_ran() -> anyhow::Result<()> {
+        let eval: &[u8] = b"*3\r\n$4\r\nEVAL\r\n$6\r\nreturn\r\n:0\r\n";
+        let stub = Stub::serve(vec![
+            (eval, b"-ERR syntax error\r\n"),
+            (eval, b"+NONE\r\n"),
+            (eval, b"+NONE\r\n"),
+        ])
+        .await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        let no_keys: [&str; 0] = [];
+        let no_args: [i64; 0] = [];
+        assert!(client
+            .eval::<(), _, _>("return", &no_keys, &no_args)
+            .await
+            .is_err());
+        client
+            .eval::<(), _, _>("return", &no_keys, &no_args)
+            .await?;
+        client.forget_scripts();
+        client
+            .eval::<(), _, _>("return", &no_keys, &no_args)
+            .await?;
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_publishes_and_skips_push_frames() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![(
+            b"*3\r\n$7\r\nPUBLISH\r\n$4\r\nnews\r\n$2\r\nhi\r\n",
+            b">3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$2\r\nhi\r\n:2\r\n",
+        )])
+        .await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        assert_eq!(client.publish("news", "hi").await?, 2);
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_caches_gets_until_invalidated() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![
+            (
+                b"*3\r\n$6\r\nCLIENT\r\n$8\r\nTRACKING\r\n$2\r\nON\r\n",
+                b"+OK\r\n",
+            ),
+            (b"*2\r\n$3\r\nGET\r\n$1\r\na\r\n", b"$1\r\nx\r\n"),
+            (
+                b"*2\r\n$3\r\nGET\r\n$1\r\nb\r\n",
+                b">2\r\n$10\r\ninvalidate\r\n*1\r\n$1\r\na\r\n+NONE\r\n",
+            ),
+            (b"*2\r\n$3\r\nGET\r\n$1\r\na\r\n", b"$1\r\nz\r\n"),
+        ])
+        .await?;
+        let mut client = Client::with_config(ClientConfig::new(stub.addr())?.cache(10));
+        client.connect().await?;
+        assert_eq!(client.get::<String>("a").await?, "x");
+        assert_eq!(client.get::<String>("a").await?, "x");
+        assert_eq!(client.get::<Option<String>>("b").await?, None);
+        assert_eq!(client.get::<String>("a").await?, "z");
+        let stats = client.cache_stats().expect("cache is enabled");
+        assert_eq!((stats.hits, stats.misses, stats.invalidations), (1, 3, 1));
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_drops_keys_it_writes_from_the_cache() -> anyhow::Result<()> {
+        let get_a: &[u8] = b"*2\r\n$3\r\nGET\r\n$1\r\na\r\n";
+        let stub = Stub::serve(vec![
+            (
+                b"*3\r\n$6\r\nCLIENT\r\n$8\r\nTRACKING\r\n$2\r\nON\r\n",
+                b"+OK\r\n",
+            ),
+            (get_a, b":1\r\n"),
+            (b"*3\r\n$3\r\nSET\r\n$1\r\na\r\n:5\r\n", b"+OK\r\n"),
+            (get_a, b":5\r\n"),
+            (b"*2\r\n$4\r\nINCR\r\n$1\r\na\r\n", b":6\r\n"),
+            (get_a, b":6\r\n"),
+        ])
+        .await?;
+        let mut client = Client::with_config(ClientConfig::new(stub.addr())?.cache(10));
+        client.connect().await?;
+        assert_eq!(client.get::<i64>("a").await?, 1);
+        assert!(client.set("a", 5).await?.is_ok());
+        assert_eq!(client.get::<i64>("a").await?, 5);
+        assert_eq!(client.get::<i64>("a").await?, 5);
+        assert_eq!(client.incr("a").await?, 6);
+        assert_eq!(client.get::<i64>("a").await?, 6);
+        let stats = client.cache_stats().expect("cache is enabled");
+        assert_eq!((stats.hits, stats.invalidations), (1, 2));
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_caches_int_and_string_keys_apart() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![
+            (
+                b"*3\r\n$6\r\nCLIENT\r\n$8\r\nTRACKING\r\n$2\r\nON\r\n",
+                b"+OK\r\n",
+            ),
+            (b"*2\r\n$3\r\nGET\r\n:5\r\n", b":1\r\n"),
+            (b"*2\r\n$3\r\nGET\r\n$1\r\n5\r\n", b"$1\r\nx\r\n"),
+            (
+                b"*2\r\n$3\r\nGET\r\n$1\r\nb\r\n",
+                b">2\r\n$10\r\ninvalidate\r\n*1\r\n$1\r\n5\r\n+NONE\r\n",
+            ),
+            (b"*2\r\n$3\r\nGET\r\n:5\r\n", b":2\r\n"),
+            (b"*2\r\n$3\r\nGET\r\n$1\r\n5\r\n", b"$1\r\ny\r\n"),
+        ])
+        .await?;
+        let mut client = Client::with_config(ClientConfig::new(stub.addr())?.cache(10));
+        client.connect().await?;
+        assert_eq!(client.get::<i64>(5).await?, 1);
+        assert_eq!(client.get::<String>("5").await?, "x");
+        assert_eq!(client.get::<i64>(5).await?, 1);
+        assert_eq!(client.get::<String>("5").await?, "x");
+        // the server names both keys "5", so both are dropped
+        assert_eq!(client.get::<Option<String>>("b").await?, None);
+        assert_eq!(client.get::<i64>(5).await?, 2);
+        assert_eq!(client.get::<String>("5").await?, "y");
+        let stats = client.cache_stats().expect("cache is enabled");
+        assert_eq!((stats.hits, stats.invalidations), (2, 2));
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_refuses_to_cache_without_tracking() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![(
+            b"*3\r\n$6\r\nCLIENT\r\n$8\r\nTRACKING\r\n$2\r\nON\r\n",
+            b"-unknown command CLIENT\r\n",
+        )])
+        .await?;
+        let mut client = Client::with_config(ClientConfig::new(stub.addr())?.cache(10));
+        let err = client.connect().await.unwrap_err();
+        assert!(err.to_string().contains("CLIENT TRACKING"));
+        assert!(!client.is_connected());
+        stub.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_drops_the_cache_with_the_connection() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![
+            (
+                b"*3\r\n$6\r\nCLIENT\r\n$8\r\nTRACKING\r\n$2\r\nON\r\n",
+                b"+OK\r\n",
+            ),
+            (b"*2\r\n$3\r\nGET\r\n$1\r\na\r\n", b"$1\r\nx\r\n"),
+            (b"+PING\r\n", b"?bad\r\n"),
+        ])
+        .await?;
+        let mut client = Client::with_config(ClientConfig::new(stub.addr())?.cache(10));
+        client.connect().await?;
+        assert_eq!(client.get::<String>("a").await?, "x");
+        assert!(client.ping().await.is_err());
+        let err = client.get::<String>("a").await.unwrap_err();
+        assert_eq!(err.downcast_ref::<Error>(), Some(&Error::NotConnected));
+        let stats = client.cache_stats().expect("cache is enabled");
+        assert_eq!((stats.hits, stats.invalidations), (0, 1));
+        stub.finish().await
+    }
+
+    #[test]
+    fn config_debug_hides_the_password() -> anyhow::Result<()> {
+        let config = ClientConfig::new("127.0.0.1:6969")?.credentials("vince", "secret");
+        let debug = format!("{:?}", config);
+        assert!(debug.contains("vince"));
+        assert!(!debug.contains("secret"));
+        Ok(())
     }
 }
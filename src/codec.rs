@@ -0,0 +1,95 @@
+#[cfg(feature = "json")]
+pub use json::Json;
+
+#[cfg(feature = "json")]
+mod json {
+    use serde::{de::DeserializeOwned, Serialize};
+
+    use crate::lexi_data::{unexpected, FromLexiData, LexiData};
+
+    /// Stores any serde type as a JSON bulk string.
+    ///
+    /// ```ignore
+    /// client.set("user:1", Json(&user)).await?;
+    /// let Json(user): Json<User> = client.get("user:1").await?;
+    /// ```
+    ///
+    /// A value that fails to serialize fails the command before anything is
+    /// sent. Decoding a missing key fails; ask for an `Option<Json<T>>` when
+    /// the key may not exist.
+    #[derive(Debug, Clone, PartialEq, Eq, Default)]
+    pub struct Json<T>(pub T);
+
+    impl<T: Serialize> Json<T> {
+        /// Serializes the value into a JSON bulk string. Fails if `T`'s
+        /// `Serialize` implementation does, which for derived implementations
+        /// only happens for maps whose keys are not strings.
+        pub fn encode(&self) -> Result<LexiData, serde_json::Error> {
+            Ok(LexiData::Bulk(serde_json::to_string(&self.0)?))
+        }
+    }
+
+    impl<T: Serialize> TryFrom<Json<T>> for LexiData {
+        type Error = serde_json::Error;
+
+        fn try_from(value: Json<T>) -> Result<Self, serde_json::Error> {
+            value.encode()
+        }
+    }
+
+    impl<T: DeserializeOwned> FromLexiData for Json<T> {
+        fn from_lexi_data(data: LexiData) -> anyhow::Result<Self> {
+            match data {
+                LexiData::Bulk(s) => Ok(Json(serde_json::from_str(&s)?)),
+                other => Err(unexpected("json bulk string", other)),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use std::collections::HashMap;
+
+        use serde::{Deserialize, Serialize};
+
+        use super::Json;
+        use crate::lexi_data::{FromLexiData, LexiData, SimpleString};
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct User {
+            name: String,
+            age: u8,
+        }
+
+        #[test]
+        fn json_round_trips() -> anyhow::Result<()> {
+            let user = User {
+                name: "vince".to_owned(),
+                age: 24,
+            };
+            let data = Json(&user).encode()?;
+            assert_eq!(
+                data,
+                LexiData::Bulk(r#"{"name":"vince","age":24}"#.to_owned())
+            );
+            let Json(back) = Json::<User>::from_lexi_data(data)?;
+            assert_eq!(user, back);
+            Ok(())
+        }
+
+        #[test]
+        fn json_fails_to_encode_maps_with_non_string_keys() {
+            let map = HashMap::from([((1, 2), "a")]);
+            assert!(LexiData::try_from(Json(map)).is_err());
+        }
+
+        #[test]
+        fn json_decodes_missing_keys_as_none() -> anyhow::Result<()> {
+            let none = LexiData::Simple(SimpleString::None);
+            assert!(Json::<User>::from_lexi_data(none).is_err());
+            let none = LexiData::Simple(SimpleString::None);
+            assert_eq!(Option::<Json<User>>::from_lexi_data(none)?, None);
+            Ok(())
+        }
+    }
+}
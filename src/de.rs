@@ -0,0 +1,394 @@
+use std::fmt;
+
+use serde::{
+    de::{
+        self, value::MapDeserializer, value::SeqDeserializer, DeserializeOwned, EnumAccess,
+        IntoDeserializer, VariantAccess, Visitor,
+    },
+    forward_to_deserialize_any, ser,
+};
+
+use crate::lexi_data::{LexiData, SimpleString};
+
+/// Deserializes a response into any type implementing `Deserialize`.
+///
+/// Arrays map to sequences, tuples and structs (fields in declaration
+/// order), or to maps when read as `key, value, key, value, ...`. Bulk
+/// strings map to strings and bytes, and are parsed when a number or bool
+/// is asked for. `NONE` maps to `Option::None` and unit, and server errors
+/// are returned as `Err`.
+///
+/// ```ignore
+/// let keys: Vec<String> = lexi_rs::from_data(client.keys().await?)?;
+/// ```
+pub fn from_data<T: DeserializeOwned>(data: LexiData) -> Result<T, Error> {
+    T::deserialize(Deserializer::new(data))
+}
+
+/// Deserializes a flat `field, value, field, value, ...` array, such as a
+/// `HGETALL` reply, into a struct by field name or into any map type.
+pub fn from_fields<T: DeserializeOwned>(data: LexiData) -> Result<T, Error> {
+    match data {
+        LexiData::Array(arr) => T::deserialize(pairs(arr)?),
+        LexiData::Error(e) => Err(Error(e)),
+        other => Err(Error(format!(
+            "expected field value pairs, got {:?}",
+            other
+        ))),
+    }
+}
+
+fn pairs<'de>(
+    arr: Vec<LexiData>,
+) -> Result<MapDeserializer<'de, impl Iterator<Item = (LexiData, LexiData)>, Error>, Error> {
+    if arr.len() % 2 != 0 {
+        return Err(de::Error::invalid_length(arr.len(), &"key value pairs"));
+    }
+    let mut iter = arr.into_iter();
+    let pairs = std::iter::from_fn(move || Some((iter.next()?, iter.next()?)));
+    Ok(MapDeserializer::new(pairs))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+pub struct Deserializer {
+    data: LexiData,
+}
+
+impl Deserializer {
+    pub fn new(data: LexiData) -> Self {
+        Deserializer { data }
+    }
+
+    fn parse_bulk<T: std::str::FromStr>(s: &str, exp: &str) -> Result<T, Error> {
+        s.parse()
+            .map_err(|_| de::Error::invalid_value(de::Unexpected::Str(s), &exp))
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for LexiData {
+    type Deserializer = Deserializer;
+
+    fn into_deserializer(self) -> Deserializer {
+        Deserializer::new(self)
+    }
+}
+
+macro_rules! deserialize_number {
+    ($method:ident, $visit:ident, $ty:ty) => {
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            match self.data {
+                LexiData::Bulk(s) => visitor.$visit(Self::parse_bulk::<$ty>(&s, stringify!($ty))?),
+                _ => self.deserialize_any(visitor),
+            }
+        }
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.data {
+            LexiData::Simple(SimpleString::Ok) => visitor.visit_str("OK"),
+            LexiData::Simple(SimpleString::Pong) => visitor.visit_str("PONG"),
+            LexiData::Simple(SimpleString::Queued) => visitor.visit_str("QUEUED"),
+            LexiData::Simple(SimpleString::None) => visitor.visit_none(),
+            LexiData::Int(i) => visitor.visit_i64(i),
+            LexiData::Double(d) => visitor.visit_f64(d),
+            LexiData::Bulk(s) => visitor.visit_string(s),
+            LexiData::Error(e) => Err(Error(e)),
+            LexiData::Array(arr) | LexiData::Push(arr) => {
+                let mut seq = SeqDeserializer::new(arr.into_iter());
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+        }
+    }
+
+    deserialize_number!(deserialize_i8, visit_i8, i8);
+    deserialize_number!(deserialize_i16, visit_i16, i16);
+    deserialize_number!(deserialize_i32, visit_i32, i32);
+    deserialize_number!(deserialize_i64, visit_i64, i64);
+    deserialize_number!(deserialize_u8, visit_u8, u8);
+    deserialize_number!(deserialize_u16, visit_u16, u16);
+    deserialize_number!(deserialize_u32, visit_u32, u32);
+    deserialize_number!(deserialize_u64, visit_u64, u64);
+    deserialize_number!(deserialize_f32, visit_f32, f32);
+    deserialize_number!(deserialize_f64, visit_f64, f64);
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.data {
+            LexiData::Int(0) => visitor.visit_bool(false),
+            LexiData::Int(1) => visitor.visit_bool(true),
+            LexiData::Bulk(s) => match s.as_str() {
+                "0" | "false" => visitor.visit_bool(false),
+                "1" | "true" => visitor.visit_bool(true),
+                _ => Err(de::Error::invalid_value(de::Unexpected::Str(&s), &"bool")),
+            },
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.data {
+            LexiData::Bulk(s) => visitor.visit_byte_buf(s.into_bytes()),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.data {
+            LexiData::Simple(SimpleString::None) => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.data {
+            LexiData::Simple(SimpleString::Ok) | LexiData::Simple(SimpleString::None) => {
+                visitor.visit_unit()
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.data {
+            LexiData::Array(arr) => {
+                let mut map = pairs(arr)?;
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.data {
+            LexiData::Bulk(s) => visitor.visit_enum(s.into_deserializer()),
+            LexiData::Array(arr) => match <[LexiData; 2]>::try_from(arr) {
+                Ok([variant, value]) => visitor.visit_enum(Enum { variant, value }),
+                Err(arr) => Deserializer::new(LexiData::Array(arr)).deserialize_any(visitor),
+            },
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        char str string seq tuple tuple_struct struct identifier ignored_any
+    }
+}
+
+struct Enum {
+    variant: LexiData,
+    value: LexiData,
+}
+
+impl<'de> EnumAccess<'de> for Enum {
+    type Error = Error;
+    type Variant = Deserializer;
+
+    fn variant_seed<S: de::DeserializeSeed<'de>>(
+        self,
+        seed: S,
+    ) -> Result<(S::Value, Deserializer), Error> {
+        let variant = seed.deserialize(Deserializer::new(self.variant))?;
+        Ok((variant, Deserializer::new(self.value)))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Deserializer {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        de::Deserialize::deserialize(self)
+    }
+
+    fn newtype_variant_seed<S: de::DeserializeSeed<'de>>(self, seed: S) -> Result<S::Value, Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use serde::Deserialize;
+
+    use super::{from_data, from_fields};
+    use crate::lexi_data::{LexiData, SimpleString};
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Key(String);
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Entry {
+        name: String,
+        score: f64,
+        rank: u32,
+        nickname: Option<String>,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum Color {
+        Red,
+        Custom(String),
+    }
+
+    #[test]
+    fn it_deserializes_sequences() -> anyhow::Result<()> {
+        let data = LexiData::Array(vec!["foo".into(), "bar".into()]);
+        let keys: Vec<Key> = from_data(data)?;
+        assert_eq!(keys, vec![Key("foo".to_owned()), Key("bar".to_owned())]);
+
+        let data = LexiData::Array(vec!["foo".into(), LexiData::Int(5)]);
+        let tuple: (String, i64) = from_data(data)?;
+        assert_eq!(tuple, ("foo".to_owned(), 5));
+        Ok(())
+    }
+
+    #[test]
+    fn it_deserializes_structs_from_arrays() -> anyhow::Result<()> {
+        let data = LexiData::Array(vec![
+            "vince".into(),
+            LexiData::Double(13.37),
+            "3".into(),
+            LexiData::Simple(SimpleString::None),
+        ]);
+        let entry: Entry = from_data(data)?;
+        assert_eq!(
+            entry,
+            Entry {
+                name: "vince".to_owned(),
+                score: 13.37,
+                rank: 3,
+                nickname: None,
+            }
+        );
+
+        let data = LexiData::Array(vec!["vince".into()]);
+        assert!(from_data::<Entry>(data).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_deserializes_maps_from_pairs() -> anyhow::Result<()> {
+        let data = LexiData::Array(vec!["a".into(), LexiData::Int(1), "b".into(), "2".into()]);
+        let map: HashMap<String, i64> = from_data(data)?;
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["a"], 1);
+        assert_eq!(map["b"], 2);
+
+        let data = LexiData::Array(vec!["a".into()]);
+        assert!(from_data::<HashMap<String, i64>>(data).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_deserializes_structs_from_fields() -> anyhow::Result<()> {
+        let data = LexiData::Array(vec![
+            "rank".into(),
+            "3".into(),
+            "name".into(),
+            "vince".into(),
+            "score".into(),
+            "13.37".into(),
+        ]);
+        let entry: Entry = from_fields(data)?;
+        assert_eq!(
+            entry,
+            Entry {
+                name: "vince".to_owned(),
+                score: 13.37,
+                rank: 3,
+                nickname: None,
+            }
+        );
+        let data = LexiData::Array(vec!["name".into(), "vince".into()]);
+        assert!(from_fields::<Entry>(data).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_deserializes_enums() -> anyhow::Result<()> {
+        assert_eq!(from_data::<Color>("Red".into())?, Color::Red);
+        let data = LexiData::Array(vec!["Custom".into(), "teal".into()]);
+        assert_eq!(from_data::<Color>(data)?, Color::Custom("teal".to_owned()));
+        Ok(())
+    }
+
+    #[test]
+    fn it_deserializes_simple_strings() -> anyhow::Result<()> {
+        let none: Option<String> = from_data(LexiData::Simple(SimpleString::None))?;
+        assert_eq!(none, None);
+        from_data::<()>(LexiData::Simple(SimpleString::Ok))?;
+        Ok(())
+    }
+
+    #[test]
+    fn it_returns_server_errors() {
+        let err = from_data::<String>(LexiData::Error("invalid command".to_owned()));
+        assert_eq!(err.unwrap_err().to_string(), "invalid command");
+    }
+}
@@ -0,0 +1,251 @@
+use bytes::BufMut;
+
+use crate::lexi_data::{LexiData, SimpleString};
+
+/// Writes frames straight into a caller supplied buffer.
+///
+/// The encoder borrows the buffer instead of owning one, so a single
+/// `BytesMut` (or `Vec<u8>`) can be cleared and reused for every command
+/// and pipeline sent over a connection.
+pub struct Encoder<'a, B: BufMut> {
+    buf: &'a mut B,
+}
+
+enum TypeByte {
+    Array,
+    Push,
+    Bulk,
+    Int,
+    Double,
+    Simple,
+    Error,
+}
+
+impl<'a, B: BufMut> Encoder<'a, B> {
+    pub fn new(buf: &'a mut B) -> Self {
+        Encoder { buf }
+    }
+
+    pub fn add_ping(&mut self) -> &mut Self {
+        self.add_type_byte(TypeByte::Simple);
+        self.buf.put_slice(b"PING");
+        self.add_end();
+        self
+    }
+
+    pub fn add_arr(&mut self, len: usize) -> &mut Self {
+        self.add_type_byte(TypeByte::Array);
+        self.add_len(len);
+        self.add_end();
+        self
+    }
+
+    pub fn add_bulk(&mut self, bulk: &str) -> &mut Self {
+        self.add_type_byte(TypeByte::Bulk);
+        self.add_len(bulk.len());
+        self.add_end();
+        self.buf.put_slice(bulk.as_bytes());
+        self.add_end();
+        self
+    }
+
+    pub fn add_int(&mut self, int: i64) -> &mut Self {
+        self.add_type_byte(TypeByte::Int);
+        let mut fmt = itoa::Buffer::new();
+        self.buf.put_slice(fmt.format(int).as_bytes());
+        self.add_end();
+        self
+    }
+
+    /// Finite values are written in their shortest round-trip form, so
+    /// parsing the frame yields the exact same bits. Infinities and NaN are
+    /// written as `inf`, `-inf` and `nan`, the spellings lexidb uses.
+    pub fn add_double(&mut self, dbl: f64) -> &mut Self {
+        self.add_type_byte(TypeByte::Double);
+        if dbl.is_nan() {
+            self.buf.put_slice(b"nan");
+        } else if dbl.is_infinite() {
+            let s: &[u8] = if dbl.is_sign_positive() {
+                b"inf"
+            } else {
+                b"-inf"
+            };
+            self.buf.put_slice(s);
+        } else {
+            let mut fmt = ryu::Buffer::new();
+            self.buf.put_slice(fmt.format_finite(dbl).as_bytes());
+        }
+        self.add_end();
+        self
+    }
+
+    pub fn add_simple(&mut self, simple: &SimpleString) -> &mut Self {
+        self.add_type_byte(TypeByte::Simple);
+        let s: &[u8] = match simple {
+            SimpleString::Ok => b"OK",
+            SimpleString::Pong => b"PONG",
+            SimpleString::None => b"NONE",
+            SimpleString::Queued => b"QUEUED",
+        };
+        self.buf.put_slice(s);
+        self.add_end();
+        self
+    }
+
+    pub fn add_error(&mut self, err: &str) -> &mut Self {
+        self.add_type_byte(TypeByte::Error);
+        self.buf.put_slice(err.as_bytes());
+        self.add_end();
+        self
+    }
+
+    pub fn add_data(&mut self, data: &LexiData) -> &mut Self {
+        match data {
+            LexiData::Simple(s) => self.add_simple(s),
+            LexiData::Int(i) => self.add_int(*i),
+            LexiData::Double(d) => self.add_double(*d),
+            LexiData::Bulk(s) => self.add_bulk(s),
+            LexiData::Error(e) => self.add_error(e),
+            LexiData::Array(arr) => {
+                self.add_arr(arr.len());
+                for item in arr {
+                    self.add_data(item);
+                }
+                self
+            }
+            LexiData::Push(arr) => {
+                self.add_type_byte(TypeByte::Push);
+                self.add_len(arr.len());
+                self.add_end();
+                for item in arr {
+                    self.add_data(item);
+                }
+                self
+            }
+        }
+    }
+
+    pub fn add_impl_lexi_data(&mut self, value: impl Into<LexiData>) -> &mut Self {
+        self.add_data(&value.into())
+    }
+
+    fn add_type_byte(&mut self, type_byte: TypeByte) {
+        match type_byte {
+            TypeByte::Array => self.buf.put_u8(b'*'),
+            TypeByte::Push => self.buf.put_u8(b'>'),
+            TypeByte::Bulk => self.buf.put_u8(b'$'),
+            TypeByte::Int => self.buf.put_u8(b':'),
+            TypeByte::Double => self.buf.put_u8(b','),
+            TypeByte::Simple => self.buf.put_u8(b'+'),
+            TypeByte::Error => self.buf.put_u8(b'-'),
+        }
+    }
+
+    fn add_len(&mut self, len: usize) {
+        let mut fmt = itoa::Buffer::new();
+        self.buf.put_slice(fmt.format(len).as_bytes());
+    }
+
+    fn add_end(&mut self) {
+        self.buf.put_slice(b"\r\n");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::BytesMut;
+
+    use crate::lexi_data::{LexiData, SimpleString};
+
+    use super::Encoder;
+
+    #[test]
+    fn encoder_can_add_strings() {
+        let mut buf = Vec::new();
+        Encoder::new(&mut buf).add_bulk("vince");
+        let buf_str = String::from_utf8(buf).unwrap();
+        assert_eq!(buf_str, "$5\r\nvince\r\n");
+    }
+
+    #[test]
+    fn encoder_can_add_multibyte_strings() {
+        let mut buf = Vec::new();
+        Encoder::new(&mut buf).add_bulk("héllo");
+        let buf_str = String::from_utf8(buf).unwrap();
+        assert_eq!(buf_str, "$6\r\nhéllo\r\n");
+    }
+
+    #[test]
+    fn encoder_can_add_arrays() {
+        let mut buf = Vec::new();
+        Encoder::new(&mut buf)
+            .add_arr(2)
+            .add_bulk("vince")
+            .add_bulk("is cool");
+        let buf_str = String::from_utf8(buf).unwrap();
+        assert_eq!(buf_str, "*2\r\n$5\r\nvince\r\n$7\r\nis cool\r\n");
+    }
+
+    #[test]
+    fn encoder_can_add_integers() {
+        let mut buf = Vec::new();
+        Encoder::new(&mut buf).add_int(42069);
+        let t = vec![b':', b'4', b'2', b'0', b'6', b'9', b'\r', b'\n'];
+        assert_eq!(t, buf);
+    }
+
+    #[test]
+    fn encoder_can_add_doubles() {
+        let mut buf = Vec::new();
+        Encoder::new(&mut buf).add_double(1337.1337);
+        let t = vec![
+            b',', b'1', b'3', b'3', b'7', b'.', b'1', b'3', b'3', b'7', b'\r', b'\n',
+        ];
+        assert_eq!(buf, t);
+    }
+
+    #[test]
+    fn encoder_can_add_special_doubles() {
+        let tests: [(f64, &str); 6] = [
+            (f64::INFINITY, ",inf\r\n"),
+            (f64::NEG_INFINITY, ",-inf\r\n"),
+            (f64::NAN, ",nan\r\n"),
+            (-0.0, ",-0.0\r\n"),
+            (1e300, ",1e300\r\n"),
+            (5e-324, ",5e-324\r\n"),
+        ];
+
+        for (dbl, exp) in tests {
+            let mut buf = Vec::new();
+            Encoder::new(&mut buf).add_double(dbl);
+            assert_eq!(String::from_utf8(buf).unwrap(), exp);
+        }
+    }
+
+    #[test]
+    fn encoder_can_add_nested_data() {
+        let mut buf = Vec::new();
+        let data = LexiData::Array(vec![
+            LexiData::Simple(SimpleString::Ok),
+            LexiData::Int(-1),
+            LexiData::Array(vec!["foo".into()]),
+            LexiData::Error("bad".to_owned()),
+        ]);
+        Encoder::new(&mut buf).add_data(&data);
+        let buf_str = String::from_utf8(buf).unwrap();
+        assert_eq!(buf_str, "*4\r\n+OK\r\n:-1\r\n*1\r\n$3\r\nfoo\r\n-bad\r\n");
+    }
+
+    #[test]
+    fn encoder_can_reuse_a_buffer() {
+        let mut buf = BytesMut::new();
+        Encoder::new(&mut buf)
+            .add_arr(2)
+            .add_bulk("GET")
+            .add_bulk("foo");
+        assert_eq!(&buf[..], b"*2\r\n$3\r\nGET\r\n$3\r\nfoo\r\n");
+        buf.clear();
+        Encoder::new(&mut buf).add_ping();
+        assert_eq!(&buf[..], b"+PING\r\n");
+    }
+}
@@ -0,0 +1,42 @@
+use std::fmt;
+
+/// Errors raised by the client itself rather than by i/o or parsing.
+///
+/// Client methods return `anyhow::Result`; match on these with
+/// `err.downcast_ref::<Error>()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The server rejected the credentials passed to `AUTH`.
+    AuthFailed(String),
+    /// The server answered a command with an error.
+    Server(String),
+    /// A command was sent before `connect` succeeded.
+    NotConnected,
+    /// The server closed the connection while a reply was awaited.
+    ConnectionClosed,
+    /// `Client::transaction` gave up after its watched keys kept changing.
+    TransactionConflict,
+    /// A `ShardedClient` was asked to route a key with no nodes to route to.
+    NoNodes,
+    /// `ShardedClient::add_node` was given a node it already has.
+    DuplicateNode(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::AuthFailed(e) => write!(f, "authentication failed: {}", e),
+            Error::Server(e) => f.write_str(e),
+            Error::NotConnected => f.write_str("not connected"),
+            Error::ConnectionClosed => f.write_str("connection closed by server"),
+            Error::TransactionConflict => {
+                f.write_str("transaction aborted, watched keys kept changing")
+            }
+            Error::NoNodes => f.write_str("sharded client has no nodes"),
+            Error::DuplicateNode(node) => write!(f, "{} is already a node", node),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
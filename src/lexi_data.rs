@@ -1,82 +1,759 @@
-#[derive(Debug, PartialEq, Eq, Hash)]
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    collections::HashSet,
+    fmt,
+    hash::{Hash, Hasher},
+    num::TryFromIntError,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
 pub enum SimpleString {
     Ok,
     Pong,
     None,
+    /// A command accepted into a transaction, to run on `EXEC`.
+    Queued,
 }
 
-#[derive(Debug, PartialEq)]
+/// A value sent to or received from lexidb.
+///
+/// With serde, every value serializes as a single-key object naming its
+/// type, which is the shape used for JSON and which will not change:
+///
+/// ```json
+/// {"simple": "ok"}
+/// {"int": 5}
+/// {"double": 1.5}
+/// {"bulk": "foo"}
+/// {"error": "invalid command"}
+/// {"array": [{"bulk": "foo"}, {"int": 5}]}
+/// {"push": [{"bulk": "message"}, {"bulk": "news"}, {"bulk": "hi"}]}
+/// ```
+///
+/// `Push` frames are sent by the server on its own, outside of any reply,
+/// for example pub/sub messages.
+///
+/// Simple strings are `"ok"`, `"pong"`, `"none"` or `"queued"`. Human
+/// readable formats such as JSON have no way of writing infinities or NaN
+/// as numbers, so those doubles are written as the strings `"inf"`,
+/// `"-inf"` and `"nan"`.
+///
+/// `LexiData` is `Eq`, `Ord` and `Hash`, so responses can be used in sets,
+/// sorted or used as map keys:
+///
+/// - values of different types are never equal and order by type, in the
+///   order the variants are declared (`Simple` < `Int` < `Double` < `Bulk` <
+///   `Error` < `Array` < `Push`), so `Int(1)` and `Double(1.0)` are distinct
+/// - doubles compare by their bits using `f64::total_cmp`: a NaN equals
+///   itself, `-0.0` is less than `0.0`, and NaN sorts after infinity
+/// - arrays and pushes compare element by element, then by length
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
 pub enum LexiData {
     Simple(SimpleString),
     Int(i64),
-    Double(f64),
+    Double(#[serde(with = "double")] f64),
     Bulk(String),
     Error(String),
     Array(Vec<LexiData>),
+    Push(Vec<LexiData>),
+}
+
+impl LexiData {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            LexiData::Bulk(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            LexiData::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            LexiData::Double(d) => Some(*d),
+            LexiData::Int(i) => Some(*i as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[LexiData]> {
+        match self {
+            LexiData::Array(arr) => Some(arr),
+            _ => None,
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        matches!(self, LexiData::Simple(SimpleString::Ok))
+    }
+
+    pub fn is_none(&self) -> bool {
+        matches!(self, LexiData::Simple(SimpleString::None))
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(self, LexiData::Error(_))
+    }
+
+    /// Turns a server error into `Err`, passing every other value through.
+    pub fn into_result(self) -> anyhow::Result<LexiData> {
+        match self {
+            LexiData::Error(e) => Err(Error::Server(e).into()),
+            other => Ok(other),
+        }
+    }
+
+    fn fmt_indented(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        match self {
+            LexiData::Simple(s) => write!(f, "{}", s),
+            LexiData::Int(i) => write!(f, "(integer) {}", i),
+            LexiData::Double(d) => write!(f, "(double) {}", d),
+            LexiData::Bulk(s) => write!(f, "{:?}", s),
+            LexiData::Error(e) => write!(f, "(error) {}", e),
+            LexiData::Array(arr) if arr.is_empty() => write!(f, "(empty array)"),
+            LexiData::Push(arr) if arr.is_empty() => write!(f, "(empty push)"),
+            LexiData::Array(arr) | LexiData::Push(arr) => {
+                let width = arr.len().to_string().len();
+                for (i, item) in arr.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "\n{:indent$}", "")?;
+                    }
+                    write!(f, "{:>width$}) ", i + 1)?;
+                    item.fmt_indented(f, indent + width + 2)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl LexiData {
+    fn type_order(&self) -> u8 {
+        match self {
+            LexiData::Simple(_) => 0,
+            LexiData::Int(_) => 1,
+            LexiData::Double(_) => 2,
+            LexiData::Bulk(_) => 3,
+            LexiData::Error(_) => 4,
+            LexiData::Array(_) => 5,
+            LexiData::Push(_) => 6,
+        }
+    }
+}
+
+impl PartialEq for LexiData {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for LexiData {}
+
+impl PartialOrd for LexiData {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for LexiData {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (LexiData::Simple(a), LexiData::Simple(b)) => a.cmp(b),
+            (LexiData::Int(a), LexiData::Int(b)) => a.cmp(b),
+            (LexiData::Double(a), LexiData::Double(b)) => a.total_cmp(b),
+            (LexiData::Bulk(a), LexiData::Bulk(b)) => a.cmp(b),
+            (LexiData::Error(a), LexiData::Error(b)) => a.cmp(b),
+            (LexiData::Array(a), LexiData::Array(b)) => a.cmp(b),
+            (LexiData::Push(a), LexiData::Push(b)) => a.cmp(b),
+            _ => self.type_order().cmp(&other.type_order()),
+        }
+    }
+}
+
+impl Hash for LexiData {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.type_order().hash(state);
+        match self {
+            LexiData::Simple(s) => s.hash(state),
+            LexiData::Int(i) => i.hash(state),
+            LexiData::Double(d) => d.to_bits().hash(state),
+            LexiData::Bulk(s) => s.hash(state),
+            LexiData::Error(e) => e.hash(state),
+            LexiData::Array(arr) | LexiData::Push(arr) => arr.hash(state),
+        }
+    }
+}
+
+impl fmt::Display for SimpleString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimpleString::Ok => f.write_str("OK"),
+            SimpleString::Pong => f.write_str("PONG"),
+            SimpleString::None => f.write_str("(nil)"),
+            SimpleString::Queued => f.write_str("QUEUED"),
+        }
+    }
 }
 
-impl Into<LexiData> for &str {
-    fn into(self) -> LexiData {
-        LexiData::Bulk(self.to_string())
+/// Renders values the way a command line client would, with bulk strings
+/// quoted, other types labelled and array entries numbered:
+///
+/// ```text
+/// 1) "foo"
+/// 2) (integer) 5
+/// 3) 1) "nested"
+/// ```
+impl fmt::Display for LexiData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_indented(f, 0)
     }
 }
 
-impl Into<LexiData> for String {
-    fn into(self) -> LexiData {
-        LexiData::Bulk(self)
+impl From<&str> for LexiData {
+    fn from(value: &str) -> Self {
+        LexiData::Bulk(value.to_string())
     }
 }
 
-impl Into<LexiData> for i64 {
-    fn into(self) -> LexiData {
-        LexiData::Int(self)
+impl From<String> for LexiData {
+    fn from(value: String) -> Self {
+        LexiData::Bulk(value)
     }
 }
 
-impl Into<LexiData> for i32 {
-    fn into(self) -> LexiData {
-        LexiData::Int(self as i64)
+impl From<i64> for LexiData {
+    fn from(value: i64) -> Self {
+        LexiData::Int(value)
     }
 }
 
-impl Into<LexiData> for i16 {
-    fn into(self) -> LexiData {
-        LexiData::Int(self as i64)
+impl From<i32> for LexiData {
+    fn from(value: i32) -> Self {
+        LexiData::Int(value as i64)
     }
 }
 
-impl Into<LexiData> for i8 {
-    fn into(self) -> LexiData {
-        LexiData::Int(self as i64)
+impl From<i16> for LexiData {
+    fn from(value: i16) -> Self {
+        LexiData::Int(value as i64)
     }
 }
 
-impl Into<LexiData> for u32 {
-    fn into(self) -> LexiData {
-        LexiData::Int(self as i64)
+impl From<i8> for LexiData {
+    fn from(value: i8) -> Self {
+        LexiData::Int(value as i64)
     }
 }
 
-impl Into<LexiData> for u16 {
-    fn into(self) -> LexiData {
-        LexiData::Int(self as i64)
+impl From<u32> for LexiData {
+    fn from(value: u32) -> Self {
+        LexiData::Int(value as i64)
     }
 }
 
-impl Into<LexiData> for u8 {
-    fn into(self) -> LexiData {
-        LexiData::Int(self as i64)
+impl From<u16> for LexiData {
+    fn from(value: u16) -> Self {
+        LexiData::Int(value as i64)
     }
 }
 
-impl Into<LexiData> for f64 {
-    fn into(self) -> LexiData {
-        LexiData::Double(self)
+impl From<u8> for LexiData {
+    fn from(value: u8) -> Self {
+        LexiData::Int(value as i64)
     }
 }
 
-impl Into<LexiData> for f32 {
-    fn into(self) -> LexiData {
-        LexiData::Double(self as f64)
+impl From<f64> for LexiData {
+    fn from(value: f64) -> Self {
+        LexiData::Double(value)
+    }
+}
+
+impl From<f32> for LexiData {
+    fn from(value: f32) -> Self {
+        LexiData::Double(value as f64)
+    }
+}
+
+impl From<isize> for LexiData {
+    fn from(value: isize) -> Self {
+        LexiData::Int(value as i64)
+    }
+}
+
+impl TryFrom<u64> for LexiData {
+    type Error = TryFromIntError;
+
+    fn try_from(value: u64) -> Result<Self, TryFromIntError> {
+        Ok(LexiData::Int(i64::try_from(value)?))
+    }
+}
+
+impl TryFrom<usize> for LexiData {
+    type Error = TryFromIntError;
+
+    fn try_from(value: usize) -> Result<Self, TryFromIntError> {
+        Ok(LexiData::Int(i64::try_from(value)?))
+    }
+}
+
+impl TryFrom<i128> for LexiData {
+    type Error = TryFromIntError;
+
+    fn try_from(value: i128) -> Result<Self, TryFromIntError> {
+        Ok(LexiData::Int(i64::try_from(value)?))
+    }
+}
+
+impl TryFrom<u128> for LexiData {
+    type Error = TryFromIntError;
+
+    fn try_from(value: u128) -> Result<Self, TryFromIntError> {
+        Ok(LexiData::Int(i64::try_from(value)?))
+    }
+}
+
+impl From<bool> for LexiData {
+    fn from(value: bool) -> Self {
+        LexiData::Int(value as i64)
+    }
+}
+
+impl From<char> for LexiData {
+    fn from(value: char) -> Self {
+        LexiData::Bulk(value.to_string())
+    }
+}
+
+impl From<&String> for LexiData {
+    fn from(value: &String) -> Self {
+        LexiData::Bulk(value.clone())
+    }
+}
+
+impl From<Box<str>> for LexiData {
+    fn from(value: Box<str>) -> Self {
+        LexiData::Bulk(value.into())
+    }
+}
+
+impl From<Cow<'_, str>> for LexiData {
+    fn from(value: Cow<'_, str>) -> Self {
+        LexiData::Bulk(value.into_owned())
+    }
+}
+
+impl<T: Into<LexiData>> From<Vec<T>> for LexiData {
+    fn from(value: Vec<T>) -> Self {
+        LexiData::Array(value.into_iter().map(Into::into).collect())
+    }
+}
+
+/// `None` is sent as the `NONE` simple string.
+impl<T: Into<LexiData>> From<Option<T>> for LexiData {
+    fn from(value: Option<T>) -> Self {
+        match value {
+            Some(v) => v.into(),
+            None => LexiData::Simple(SimpleString::None),
+        }
+    }
+}
+
+/// Conversion of a value to send into `LexiData`, for values that may fail
+/// to encode such as `codec::Json`. Every `Into<LexiData>` type has it.
+pub trait TryIntoLexiData {
+    fn try_into_lexi_data(self) -> anyhow::Result<LexiData>;
+}
+
+impl<T> TryIntoLexiData for T
+where
+    T: TryInto<LexiData>,
+    T::Error: std::error::Error + Send + Sync + 'static,
+{
+    fn try_into_lexi_data(self) -> anyhow::Result<LexiData> {
+        Ok(self.try_into()?)
+    }
+}
+
+/// Conversion from a response into a rust type.
+///
+/// Server errors are turned into `Err` for every type except `LexiData`
+/// itself, which returns the response untouched.
+pub trait FromLexiData: Sized {
+    fn from_lexi_data(data: LexiData) -> anyhow::Result<Self>;
+}
+
+impl FromLexiData for LexiData {
+    fn from_lexi_data(data: LexiData) -> anyhow::Result<Self> {
+        Ok(data)
+    }
+}
+
+impl FromLexiData for String {
+    fn from_lexi_data(data: LexiData) -> anyhow::Result<Self> {
+        match data {
+            LexiData::Bulk(s) => Ok(s),
+            other => Err(unexpected("bulk string", other)),
+        }
+    }
+}
+
+impl FromLexiData for i64 {
+    fn from_lexi_data(data: LexiData) -> anyhow::Result<Self> {
+        match data {
+            LexiData::Int(i) => Ok(i),
+            other => Err(unexpected("integer", other)),
+        }
+    }
+}
+
+impl FromLexiData for usize {
+    fn from_lexi_data(data: LexiData) -> anyhow::Result<Self> {
+        Ok(usize::try_from(i64::from_lexi_data(data)?)?)
+    }
+}
+
+impl FromLexiData for f64 {
+    fn from_lexi_data(data: LexiData) -> anyhow::Result<Self> {
+        match data {
+            LexiData::Double(d) => Ok(d),
+            LexiData::Int(i) => Ok(i as f64),
+            other => Err(unexpected("double", other)),
+        }
+    }
+}
+
+/// `OK` and non-zero integers are true, `NONE` and zero are false.
+impl FromLexiData for bool {
+    fn from_lexi_data(data: LexiData) -> anyhow::Result<Self> {
+        match data {
+            LexiData::Simple(SimpleString::Ok) => Ok(true),
+            LexiData::Simple(SimpleString::None) => Ok(false),
+            LexiData::Int(i) => Ok(i != 0),
+            other => Err(unexpected("boolean", other)),
+        }
+    }
+}
+
+/// Accepts any reply that is not an error, for commands answered with `OK`.
+impl FromLexiData for () {
+    fn from_lexi_data(data: LexiData) -> anyhow::Result<Self> {
+        data.into_result().map(|_| ())
+    }
+}
+
+impl<T: FromLexiData> FromLexiData for Option<T> {
+    fn from_lexi_data(data: LexiData) -> anyhow::Result<Self> {
+        match data {
+            LexiData::Simple(SimpleString::None) => Ok(None),
+            other => T::from_lexi_data(other).map(Some),
+        }
+    }
+}
+
+impl<T: FromLexiData> FromLexiData for Vec<T> {
+    fn from_lexi_data(data: LexiData) -> anyhow::Result<Self> {
+        match data {
+            LexiData::Array(arr) => arr.into_iter().map(T::from_lexi_data).collect(),
+            other => Err(unexpected("array", other)),
+        }
+    }
+}
+
+impl<T: FromLexiData + Eq + Hash> FromLexiData for HashSet<T> {
+    fn from_lexi_data(data: LexiData) -> anyhow::Result<Self> {
+        match data {
+            LexiData::Array(arr) => arr.into_iter().map(T::from_lexi_data).collect(),
+            other => Err(unexpected("array", other)),
+        }
+    }
+}
+
+pub(crate) fn unexpected(expected: &str, got: LexiData) -> anyhow::Error {
+    match got {
+        LexiData::Error(e) => Error::Server(e).into(),
+        other => anyhow::anyhow!("expected {}, got {:?}", expected, other),
+    }
+}
+
+mod double {
+    use std::fmt;
+
+    use serde::{de, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(dbl: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if !serializer.is_human_readable() || dbl.is_finite() {
+            return serializer.serialize_f64(*dbl);
+        }
+        if dbl.is_nan() {
+            serializer.serialize_str("nan")
+        } else if dbl.is_sign_positive() {
+            serializer.serialize_str("inf")
+        } else {
+            serializer.serialize_str("-inf")
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(DoubleVisitor)
+        } else {
+            deserializer.deserialize_f64(DoubleVisitor)
+        }
+    }
+
+    struct DoubleVisitor;
+
+    impl<'de> de::Visitor<'de> for DoubleVisitor {
+        type Value = f64;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a number or one of \"inf\", \"-inf\", \"nan\"")
+        }
+
+        fn visit_f64<E: de::Error>(self, v: f64) -> Result<f64, E> {
+            Ok(v)
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<f64, E> {
+            Ok(v as f64)
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<f64, E> {
+            Ok(v as f64)
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<f64, E> {
+            match v {
+                "inf" => Ok(f64::INFINITY),
+                "-inf" => Ok(f64::NEG_INFINITY),
+                "nan" => Ok(f64::NAN),
+                _ => Err(E::invalid_value(de::Unexpected::Str(v), &self)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{borrow::Cow, collections::HashSet};
+
+    use super::{FromLexiData, LexiData, SimpleString};
+
+    #[test]
+    fn it_converts_responses_to_rust_types() -> anyhow::Result<()> {
+        assert_eq!(String::from_lexi_data("foo".into())?, "foo");
+        assert_eq!(i64::from_lexi_data(LexiData::Int(5))?, 5);
+        assert_eq!(usize::from_lexi_data(LexiData::Int(5))?, 5);
+        assert!(usize::from_lexi_data(LexiData::Int(-1)).is_err());
+        assert_eq!(f64::from_lexi_data(LexiData::Int(5))?, 5.0);
+        assert!(bool::from_lexi_data(LexiData::Int(1))?);
+        assert!(bool::from_lexi_data(LexiData::Simple(SimpleString::Ok))?);
+        assert!(!bool::from_lexi_data(LexiData::Simple(SimpleString::None))?);
+        assert_eq!(
+            Option::<String>::from_lexi_data(LexiData::Simple(SimpleString::None))?,
+            None
+        );
+        assert_eq!(
+            Vec::<String>::from_lexi_data(LexiData::Array(vec!["a".into(), "b".into()]))?,
+            vec!["a".to_owned(), "b".to_owned()]
+        );
+        assert_eq!(
+            HashSet::<String>::from_lexi_data(LexiData::Array(vec!["a".into(), "a".into()]))?,
+            HashSet::from(["a".to_owned()])
+        );
+        assert!(i64::from_lexi_data("foo".into()).is_err());
+        let err = String::from_lexi_data(LexiData::Error("invalid command".to_owned()));
+        assert_eq!(err.unwrap_err().to_string(), "invalid command");
+        Ok(())
+    }
+
+    #[test]
+    fn it_displays_like_a_cli() {
+        let tests = [
+            (LexiData::Simple(SimpleString::Ok), "OK"),
+            (LexiData::Simple(SimpleString::None), "(nil)"),
+            (LexiData::Int(5), "(integer) 5"),
+            (LexiData::Double(1.5), "(double) 1.5"),
+            (LexiData::Bulk("say \"hi\"".to_owned()), r#""say \"hi\"""#),
+            (LexiData::Error("bad".to_owned()), "(error) bad"),
+            (LexiData::Array(vec![]), "(empty array)"),
+            (
+                LexiData::Array(vec![
+                    "foo".into(),
+                    LexiData::Array(vec!["bar".into(), 1.into()]),
+                    5.into(),
+                ]),
+                "1) \"foo\"\n2) 1) \"bar\"\n   2) (integer) 1\n3) (integer) 5",
+            ),
+        ];
+
+        for (data, exp) in tests {
+            assert_eq!(exp, data.to_string());
+        }
+
+        let long = LexiData::from((0..10).collect::<Vec<i64>>()).to_string();
+        assert!(long.starts_with(" 1) (integer) 0\n 2)"));
+        assert!(long.ends_with("\n10) (integer) 9"));
+    }
+
+    #[test]
+    fn it_orders_values() {
+        let mut values = vec![
+            LexiData::Array(vec![]),
+            "b".into(),
+            LexiData::Double(f64::NAN),
+            LexiData::Double(0.0),
+            LexiData::Double(-0.0),
+            LexiData::Double(f64::INFINITY),
+            LexiData::Error("bad".to_owned()),
+            LexiData::Int(2),
+            "a".into(),
+            LexiData::Simple(SimpleString::None),
+            LexiData::Simple(SimpleString::Ok),
+        ];
+        values.sort();
+        assert_eq!(
+            values,
+            vec![
+                LexiData::Simple(SimpleString::Ok),
+                LexiData::Simple(SimpleString::None),
+                LexiData::Int(2),
+                LexiData::Double(-0.0),
+                LexiData::Double(0.0),
+                LexiData::Double(f64::INFINITY),
+                LexiData::Double(f64::NAN),
+                "a".into(),
+                "b".into(),
+                LexiData::Error("bad".to_owned()),
+                LexiData::Array(vec![]),
+            ]
+        );
+        assert!(LexiData::from(vec![1, 2]) < LexiData::from(vec![1, 3]));
+        assert!(LexiData::from(vec![1]) < LexiData::from(vec![1, 0]));
+    }
+
+    #[test]
+    fn it_hashes_consistently_with_eq() {
+        let mut set = HashSet::new();
+        assert!(set.insert(LexiData::Double(f64::NAN)));
+        assert!(!set.insert(LexiData::Double(f64::NAN)));
+        assert!(set.insert(LexiData::Double(0.0)));
+        assert!(set.insert(LexiData::Double(-0.0)));
+        assert!(set.insert(LexiData::Int(1)));
+        assert!(set.insert(LexiData::Double(1.0)));
+        assert!(set.insert(LexiData::from(vec!["a", "b"])));
+        assert!(!set.insert(LexiData::from(vec!["a", "b"])));
+        assert_eq!(set.len(), 6);
+        assert_ne!(LexiData::Int(1), LexiData::Double(1.0));
+    }
+
+    #[test]
+    fn it_has_accessors() {
+        let bulk: LexiData = "foo".into();
+        assert_eq!(bulk.as_str(), Some("foo"));
+        assert_eq!(bulk.as_i64(), None);
+        assert_eq!(LexiData::Int(5).as_i64(), Some(5));
+        assert_eq!(LexiData::Int(5).as_f64(), Some(5.0));
+        assert_eq!(LexiData::Double(0.5).as_f64(), Some(0.5));
+        let arr: LexiData = vec!["a"].into();
+        assert_eq!(arr.as_array(), Some(&["a".into()][..]));
+        assert!(LexiData::Simple(SimpleString::Ok).is_ok());
+        assert!(LexiData::Simple(SimpleString::None).is_none());
+        assert!(!bulk.is_none());
+        assert!(LexiData::Error("bad".to_owned()).is_error());
+        assert!(LexiData::Error("bad".to_owned()).into_result().is_err());
+        assert_eq!(bulk.into_result().unwrap(), "foo".into());
+    }
+
+    #[test]
+    fn it_converts_rust_types_to_data() {
+        assert_eq!(LexiData::from(true), LexiData::Int(1));
+        assert_eq!(LexiData::from(-3isize), LexiData::Int(-3));
+        assert_eq!(LexiData::from('x'), LexiData::Bulk("x".to_owned()));
+        assert_eq!(LexiData::from(&"foo".to_owned()), "foo".into());
+        assert_eq!(LexiData::from(Cow::Borrowed("foo")), "foo".into());
+        assert_eq!(LexiData::from(Box::<str>::from("foo")), "foo".into());
+        assert_eq!(
+            LexiData::from(vec![1, 2]),
+            LexiData::Array(vec![LexiData::Int(1), LexiData::Int(2)])
+        );
+        assert_eq!(
+            LexiData::from(None::<i64>),
+            LexiData::Simple(SimpleString::None)
+        );
+        assert_eq!(LexiData::from(Some(5)), LexiData::Int(5));
+    }
+
+    #[test]
+    fn it_checks_wide_integer_conversions() {
+        assert_eq!(LexiData::try_from(5u64), Ok(LexiData::Int(5)));
+        assert_eq!(
+            LexiData::try_from(i64::MAX as u64),
+            Ok(LexiData::Int(i64::MAX))
+        );
+        assert!(LexiData::try_from(i64::MAX as u64 + 1).is_err());
+        assert!(LexiData::try_from(u64::MAX).is_err());
+        assert!(LexiData::try_from(usize::MAX).is_err());
+        assert_eq!(LexiData::try_from(-5i128), Ok(LexiData::Int(-5)));
+        assert!(LexiData::try_from(i128::MIN).is_err());
+        assert!(LexiData::try_from(u128::MAX).is_err());
+    }
+
+    #[test]
+    fn it_serializes_to_json() -> anyhow::Result<()> {
+        let tests = [
+            (LexiData::Simple(SimpleString::Ok), r#"{"simple":"ok"}"#),
+            (LexiData::Simple(SimpleString::None), r#"{"simple":"none"}"#),
+            (
+                LexiData::Simple(SimpleString::Queued),
+                r#"{"simple":"queued"}"#,
+            ),
+            (LexiData::Int(-5), r#"{"int":-5}"#),
+            (LexiData::Double(1.5), r#"{"double":1.5}"#),
+            (LexiData::Double(f64::NEG_INFINITY), r#"{"double":"-inf"}"#),
+            (LexiData::Bulk("foo".to_owned()), r#"{"bulk":"foo"}"#),
+            (LexiData::Error("bad".to_owned()), r#"{"error":"bad"}"#),
+            (
+                LexiData::Array(vec!["foo".into(), 5.into()]),
+                r#"{"array":[{"bulk":"foo"},{"int":5}]}"#,
+            ),
+        ];
+
+        for (data, exp) in tests {
+            let json = serde_json::to_string(&data)?;
+            assert_eq!(exp, json);
+            let back: LexiData = serde_json::from_str(&json)?;
+            assert_eq!(data, back);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_deserializes_special_doubles_from_json() -> anyhow::Result<()> {
+        let data: LexiData = serde_json::from_str(r#"{"double":"inf"}"#)?;
+        assert_eq!(data, LexiData::Double(f64::INFINITY));
+        let data: LexiData = serde_json::from_str(r#"{"double":3}"#)?;
+        assert_eq!(data, LexiData::Double(3.0));
+        match serde_json::from_str(r#"{"double":"nan"}"#)? {
+            LexiData::Double(d) => assert!(d.is_nan()),
+            _ => unreachable!(),
+        }
+        assert!(serde_json::from_str::<LexiData>(r#"{"double":"lots"}"#).is_err());
+        Ok(())
     }
 }
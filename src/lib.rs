@@ -1,4 +1,24 @@
-mod builder;
+pub mod cache;
 pub mod client;
+pub mod codec;
+pub mod de;
+pub mod encoder;
+pub mod error;
 pub mod lexi_data;
 mod parser;
+pub mod pubsub;
+pub mod replicated;
+pub mod ser;
+pub mod sharded;
+#[cfg(test)]
+mod stub;
+pub mod transaction;
+
+pub use cache::CacheStats;
+pub use de::{from_data, from_fields};
+pub use error::Error;
+pub use pubsub::{Message, PubSub};
+pub use replicated::{ReadPreference, ReplicatedClient};
+pub use ser::to_fields;
+pub use sharded::ShardedClient;
+pub use transaction::{Queued, Transaction, TxResults};
@@ -1,20 +1,52 @@
+use std::fmt;
+
 use crate::lexi_data::{LexiData, SimpleString};
 
+/// the longest bulk string or array a frame may declare, matching the
+/// largest value lexidb accepts
+const MAX_LENGTH: usize = 512 * 1024 * 1024;
+
 pub struct Parser<'a> {
     input: &'a [u8],
     pos: usize,
     ch: u8,
+    exhausted: bool,
 }
 
+/// returned when the input ends part way through a frame
+#[derive(Debug)]
+struct Incomplete;
+
+impl fmt::Display for Incomplete {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("incomplete frame")
+    }
+}
+
+impl std::error::Error for Incomplete {}
+
 impl<'a> Parser<'a> {
     pub fn new(input: &'a [u8]) -> Self {
         let mut p = Self {
             input,
             pos: 0,
             ch: 0,
+            exhausted: false,
         };
         p.read_byte();
-        return p;
+        p
+    }
+
+    /// Parses the first frame in `input`, returning it along with the
+    /// number of bytes it took up, or `None` if more input is needed to
+    /// complete it.
+    pub fn parse_frame(input: &[u8]) -> anyhow::Result<Option<(LexiData, usize)>> {
+        let mut p = Parser::new(input);
+        match p.parse() {
+            Ok(data) => Ok(Some((data, p.consumed()))),
+            Err(e) if e.is::<Incomplete>() => Ok(None),
+            Err(e) => Err(e),
+        }
     }
 
     pub fn parse(&mut self) -> anyhow::Result<LexiData> {
@@ -24,21 +56,39 @@ impl<'a> Parser<'a> {
             b':' => self.parse_int(),
             b',' => self.parse_double(),
             b'-' => self.parse_error(),
-            b'*' => self.parse_array(),
-            _ => unreachable!(),
+            b'*' => self.parse_array().map(LexiData::Array),
+            b'>' => self.parse_array().map(LexiData::Push),
+            _ if self.exhausted => Err(Incomplete.into()),
+            ch => Err(anyhow::anyhow!("unknown type byte {:?}", ch as char)),
+        }
+    }
+
+    fn consumed(&self) -> usize {
+        if self.exhausted {
+            self.input.len()
+        } else {
+            self.pos - 1
         }
     }
 
-    fn parse_array(&mut self) -> anyhow::Result<LexiData> {
+    fn expected(&self, what: &str) -> anyhow::Error {
+        if self.exhausted {
+            return Incomplete.into();
+        }
+        anyhow::anyhow!("expected {}", what)
+    }
+
+    /// parses the items of an array or push frame
+    fn parse_array(&mut self) -> anyhow::Result<Vec<LexiData>> {
         if !self.expect_peek_to_be_num() {
-            return Err(anyhow::anyhow!("expected length"));
+            return Err(self.expected("length"));
         }
-        let length = self.parse_length();
+        let length = self.parse_length()?;
         if !self.cur_byte_is(b'\r') {
-            return Err(anyhow::anyhow!("expected retcar"));
+            return Err(self.expected("retcar"));
         }
         if !self.expect_peek(b'\n') {
-            return Err(anyhow::anyhow!("expected newline"));
+            return Err(self.expected("newline"));
         }
 
         self.read_byte();
@@ -49,39 +99,40 @@ impl<'a> Parser<'a> {
             let cur = self.parse()?;
             res.push(cur);
         }
-        return Ok(LexiData::Array(res));
+        Ok(res)
     }
 
     fn parse_string(&mut self) -> anyhow::Result<LexiData> {
-        let mut string = String::new();
         if !self.expect_peek_to_be_num() {
-            return Err(anyhow::anyhow!("expected length"));
+            return Err(self.expected("length"));
         }
-        let length = self.parse_length();
+        let length = self.parse_length()?;
 
         if !self.cur_byte_is(b'\r') {
-            return Err(anyhow::anyhow!("expected retcar"));
+            return Err(self.expected("retcar"));
         }
         if !self.expect_peek(b'\n') {
-            return Err(anyhow::anyhow!("expected newline"));
+            return Err(self.expected("newline"));
         }
 
-        self.read_byte();
-
-        for _ in 0..length {
-            string.push(self.ch as char);
-            self.read_byte();
+        // wait for the whole string and its \r\n before copying any of it
+        let start = self.pos;
+        if start + length + 2 > self.input.len() {
+            return Err(Incomplete.into());
         }
+        let bytes = self.input[start..start + length].to_vec();
+        self.pos = start + length;
+        self.read_byte();
 
         if !self.cur_byte_is(b'\r') {
-            return Err(anyhow::anyhow!("expected retcar"));
+            return Err(self.expected("retcar"));
         }
         if !self.expect_peek(b'\n') {
-            return Err(anyhow::anyhow!("expected newline"));
+            return Err(self.expected("newline"));
         }
 
         self.read_byte();
-        return Ok(LexiData::Bulk(string));
+        Ok(LexiData::Bulk(String::from_utf8(bytes)?))
     }
 
     fn parse_int(&mut self) -> anyhow::Result<LexiData> {
@@ -92,16 +143,16 @@ impl<'a> Parser<'a> {
             self.read_byte();
         }
         if !self.cur_byte_is(b'\r') {
-            return Err(anyhow::anyhow!("expected retcar"));
+            return Err(self.expected("retcar"));
         }
         if !self.expect_peek(b'\n') {
-            return Err(anyhow::anyhow!("expected newline"));
+            return Err(self.expected("newline"));
         }
 
         let res: i64 = s.parse()?;
 
         self.read_byte();
-        return Ok(LexiData::Int(res));
+        Ok(LexiData::Int(res))
     }
 
     fn parse_double(&mut self) -> anyhow::Result<LexiData> {
@@ -112,16 +163,34 @@ impl<'a> Parser<'a> {
             self.read_byte();
         }
         if !self.cur_byte_is(b'\r') {
-            return Err(anyhow::anyhow!("expected retcar"));
+            return Err(self.expected("retcar"));
         }
         if !self.expect_peek(b'\n') {
-            return Err(anyhow::anyhow!("expected newline"));
+            return Err(self.expected("newline"));
         }
 
-        let res: f64 = s.parse()?;
+        let res = Self::parse_f64(&s)?;
 
         self.read_byte();
-        return Ok(LexiData::Double(res));
+        Ok(LexiData::Double(res))
+    }
+
+    /// accepts the `inf`, `-inf` and `nan` spellings written by lexidb as
+    /// well as any decimal or exponent notation `f64` understands
+    fn parse_f64(s: &str) -> anyhow::Result<f64> {
+        if s.eq_ignore_ascii_case("inf") || s.eq_ignore_ascii_case("+inf") {
+            return Ok(f64::INFINITY);
+        }
+        if s.eq_ignore_ascii_case("-inf") {
+            return Ok(f64::NEG_INFINITY);
+        }
+        if s.eq_ignore_ascii_case("nan") {
+            return Ok(f64::NAN);
+        }
+        match s.parse() {
+            Ok(d) => Ok(d),
+            Err(_) => Err(anyhow::anyhow!("invalid double {:?}", s)),
+        }
     }
 
     fn parse_simple(&mut self) -> anyhow::Result<LexiData> {
@@ -132,21 +201,23 @@ impl<'a> Parser<'a> {
             self.read_byte();
         }
 
+        if !self.cur_byte_is(b'\r') {
+            return Err(self.expected("retcar"));
+        }
+        if !self.expect_peek(b'\n') {
+            return Err(self.expected("newline"));
+        }
+
         let simple_string = match string.as_str() {
             "OK" => SimpleString::Ok,
             "PONG" => SimpleString::Pong,
             "NONE" => SimpleString::None,
+            "QUEUED" => SimpleString::Queued,
             _ => return Err(anyhow::anyhow!("unkown simple string")),
         };
 
-        if !self.cur_byte_is(b'\r') {
-            return Err(anyhow::anyhow!("expected retcar"));
-        }
-        if !self.expect_peek(b'\n') {
-            return Err(anyhow::anyhow!("expected newline"));
-        }
         self.read_byte();
-        return Ok(LexiData::Simple(simple_string));
+        Ok(LexiData::Simple(simple_string))
     }
 
     fn parse_error(&mut self) -> anyhow::Result<LexiData> {
@@ -158,37 +229,41 @@ impl<'a> Parser<'a> {
         }
 
         if !self.cur_byte_is(b'\r') {
-            return Err(anyhow::anyhow!("expected retcar"));
+            return Err(self.expected("retcar"));
         }
         if !self.expect_peek(b'\n') {
-            return Err(anyhow::anyhow!("expected newline"));
+            return Err(self.expected("newline"));
         }
         self.read_byte();
-        return Ok(LexiData::Error(string));
+        Ok(LexiData::Error(string))
     }
 
-    fn parse_length(&mut self) -> usize {
+    fn parse_length(&mut self) -> anyhow::Result<usize> {
         let mut res: usize = 0;
         while Parser::is_digit(self.ch) {
-            res = (res * 10) + ((self.ch - b'0') as usize);
+            res = res
+                .checked_mul(10)
+                .and_then(|res| res.checked_add((self.ch - b'0') as usize))
+                .filter(|&res| res <= MAX_LENGTH)
+                .ok_or_else(|| anyhow::anyhow!("length over {} bytes", MAX_LENGTH))?;
             self.read_byte();
         }
-        return res;
+        Ok(res)
     }
 
     fn peek_byte(&self) -> u8 {
         if self.pos >= self.input.len() {
             return 0;
         }
-        return self.input[self.pos];
+        self.input[self.pos]
     }
 
     fn cur_byte_is(&self, byte: u8) -> bool {
-        return self.ch == byte;
+        self.ch == byte
     }
 
     fn peek_byte_is(&self, byte: u8) -> bool {
-        return self.peek_byte() == byte;
+        self.peek_byte() == byte
     }
 
     fn expect_peek(&mut self, byte: u8) -> bool {
@@ -196,7 +271,8 @@ impl<'a> Parser<'a> {
             self.read_byte();
             return true;
         }
-        return false;
+        self.exhausted = self.pos >= self.input.len();
+        false
     }
 
     fn expect_peek_to_be_num(&mut self) -> bool {
@@ -204,16 +280,18 @@ impl<'a> Parser<'a> {
             self.read_byte();
             return true;
         }
-        return false;
+        self.exhausted = self.pos >= self.input.len();
+        false
     }
 
     fn is_digit(ch: u8) -> bool {
-        return b'0' <= ch && ch <= b'9';
+        ch.is_ascii_digit()
     }
 
     fn read_byte(&mut self) {
         if self.pos >= self.input.len() {
             self.ch = 0;
+            self.exhausted = true;
             return;
         }
         self.ch = self.input[self.pos];
@@ -223,7 +301,10 @@ impl<'a> Parser<'a> {
 
 #[cfg(test)]
 mod test {
-    use crate::lexi_data::{LexiData, SimpleString};
+    use crate::{
+        encoder::Encoder,
+        lexi_data::{LexiData, SimpleString},
+    };
 
     use super::Parser;
 
@@ -273,6 +354,10 @@ mod test {
                 input: b"+NONE\r\n",
                 exp: SimpleString::None,
             },
+            ParserTest {
+                input: b"+QUEUED\r\n",
+                exp: SimpleString::Queued,
+            },
         ];
 
         for test in tests {
@@ -342,15 +427,15 @@ mod test {
         let tests = [
             ParserTest {
                 input: b",1337.1337\r\n",
-                exp: 1337.1337 as f64,
+                exp: 1337.1337_f64,
             },
             ParserTest {
                 input: b",1337.0\r\n",
-                exp: 1337.0 as f64,
+                exp: 1337.0_f64,
             },
             ParserTest {
                 input: b",1337\r\n",
-                exp: 1337.0 as f64,
+                exp: 1337.0_f64,
             },
         ];
 
@@ -360,13 +445,141 @@ mod test {
             assert!(matches!(data, LexiData::Double(_)));
             match data {
                 LexiData::Double(d) => assert_eq!(test.exp, d),
-                _ => assert!(false),
+                _ => unreachable!(),
             }
         }
 
         Ok(())
     }
 
+    #[test]
+    fn parse_special_doubles() -> anyhow::Result<()> {
+        let tests = [
+            ParserTest {
+                input: b",inf\r\n",
+                exp: f64::INFINITY,
+            },
+            ParserTest {
+                input: b",-inf\r\n",
+                exp: f64::NEG_INFINITY,
+            },
+            ParserTest {
+                input: b",1.7976931348623157e308\r\n",
+                exp: f64::MAX,
+            },
+            ParserTest {
+                input: b",-2.5E-10\r\n",
+                exp: -2.5e-10,
+            },
+            ParserTest {
+                input: b",5e-324\r\n",
+                exp: 5e-324,
+            },
+        ];
+
+        for test in tests {
+            let mut p = Parser::new(test.input);
+            match p.parse()? {
+                LexiData::Double(d) => assert_eq!(test.exp.to_bits(), d.to_bits()),
+                _ => unreachable!(),
+            }
+        }
+
+        let mut p = Parser::new(b",nan\r\n");
+        match p.parse()? {
+            LexiData::Double(d) => assert!(d.is_nan()),
+            _ => unreachable!(),
+        }
+
+        let mut p = Parser::new(b",1.2.3\r\n");
+        assert!(p.parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn doubles_round_trip() -> anyhow::Result<()> {
+        let tests = [
+            0.1 + 0.2,
+            -0.0,
+            1.0 / 3.0,
+            f64::MAX,
+            f64::MIN,
+            f64::MIN_POSITIVE,
+            f64::EPSILON,
+            5e-324,
+            2.225073858507201e-308,
+            1e-300,
+            123456789.98765433,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+        ];
+
+        for dbl in tests {
+            let mut buf = Vec::new();
+            Encoder::new(&mut buf).add_double(dbl);
+            let mut p = Parser::new(&buf);
+            match p.parse()? {
+                LexiData::Double(d) => assert_eq!(dbl.to_bits(), d.to_bits()),
+                _ => unreachable!(),
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn parse_frame_reports_consumed_bytes() -> anyhow::Result<()> {
+        let input = b"$3\r\nfoo\r\n:5\r\n";
+        let (data, len) = Parser::parse_frame(input)?.unwrap();
+        assert_eq!(data, "foo".into());
+        assert_eq!(len, 9);
+        let (data, len) = Parser::parse_frame(&input[len..])?.unwrap();
+        assert_eq!(data, LexiData::Int(5));
+        assert_eq!(len, 4);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_frame_waits_for_incomplete_input() -> anyhow::Result<()> {
+        let input = b"*4\r\n$3\r\nfoo\r\n$5\r\nhello\r\n+PONG\r\n-bad\r\n";
+        for end in 0..input.len() {
+            assert!(Parser::parse_frame(&input[..end])?.is_none(), "{}", end);
+        }
+        assert!(Parser::parse_frame(input)?.is_some());
+        assert!(Parser::parse_frame(b":12")?.is_none());
+        assert!(Parser::parse_frame(b",1.5\r")?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_frame_rejects_invalid_input() {
+        assert!(Parser::parse_frame(b"?foo\r\n").is_err());
+        assert!(Parser::parse_frame(b":abc\r\n").is_err());
+        assert!(Parser::parse_frame(b"$3\r\nfooo\r\n").is_err());
+        assert!(Parser::parse_frame(b"$2\r\n\xff\xfe\r\n").is_err());
+        assert!(Parser::parse_frame(b"$99999999999999999999999\r\n").is_err());
+        assert!(Parser::parse_frame(b"*99999999999999999999999\r\n").is_err());
+        assert!(Parser::parse_frame(b"$536870913\r\n").is_err());
+    }
+
+    #[test]
+    fn parse_frame_waits_for_large_strings_without_copying() -> anyhow::Result<()> {
+        let mut input = b"$200000000\r\n".to_vec();
+        input.extend_from_slice(&[b'a'; 4096]);
+        let started = std::time::Instant::now();
+        for _ in 0..100 {
+            assert!(Parser::parse_frame(&input)?.is_none());
+        }
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_multibyte_strings() -> anyhow::Result<()> {
+        let mut p = Parser::new("$6\r\nhéllo\r\n".as_bytes());
+        assert_eq!(p.parse()?, "héllo".into());
+        Ok(())
+    }
+
     #[test]
     fn parse_array() -> anyhow::Result<()> {
         let input = b"*2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n";
@@ -376,8 +589,22 @@ mod test {
         let exp: Vec<LexiData> = vec!["foo".into(), "bar".into()];
         match data {
             LexiData::Array(arr) => assert_eq!(exp, arr),
-            _ => assert!(false),
+            _ => unreachable!(),
         }
         Ok(())
     }
+
+    #[test]
+    fn it_parses_push_frames() -> anyhow::Result<()> {
+        let input = b">3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$2\r\nhi\r\n";
+        assert_eq!(
+            Parser::parse_frame(input)?,
+            Some((
+                LexiData::Push(vec!["message".into(), "news".into(), "hi".into()]),
+                input.len()
+            ))
+        );
+        assert_eq!(Parser::parse_frame(&input[..10])?, None);
+        Ok(())
+    }
 }
@@ -0,0 +1,267 @@
+use std::collections::{HashSet, VecDeque};
+
+use futures::{stream, Stream};
+
+use crate::{
+    client::{Client, ClientConfig},
+    error::Error,
+    lexi_data::{unexpected, FromLexiData, LexiData},
+};
+
+/// A message received on a subscribed channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    channel: String,
+    pattern: Option<String>,
+    payload: LexiData,
+}
+
+impl Message {
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+
+    /// The pattern that matched the channel, for messages received through
+    /// `PubSub::psubscribe`.
+    pub fn pattern(&self) -> Option<&str> {
+        self.pattern.as_deref()
+    }
+
+    pub fn payload(&self) -> &LexiData {
+        &self.payload
+    }
+
+    pub fn into_payload<T: FromLexiData>(self) -> anyhow::Result<T> {
+        T::from_lexi_data(self.payload)
+    }
+}
+
+/// A connection subscribed to channels and patterns.
+///
+/// ```ignore
+/// let mut pubsub = PubSub::connect(ClientConfig::new(<address>)?).await?;
+/// pubsub.subscribe(&["news"]).await?;
+/// let mut messages = pubsub.messages();
+/// while let Some(msg) = messages.next().await {
+///     let msg = msg?;
+///     println!("{}: {}", msg.channel(), msg.payload());
+/// }
+/// ```
+pub struct PubSub {
+    client: Client,
+    channels: HashSet<String>,
+    patterns: HashSet<String>,
+    /// messages that arrived while waiting for a (un)subscribe reply
+    pending: VecDeque<Message>,
+}
+
+/// a frame received by a subscribed connection
+enum Event {
+    Message(Message),
+    /// confirms a (un)subscribe of a single channel or pattern
+    Confirm {
+        kind: String,
+        name: Option<String>,
+    },
+}
+
+impl PubSub {
+    pub(crate) fn new(client: Client) -> Self {
+        Self {
+            client,
+            channels: HashSet::new(),
+            patterns: HashSet::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Connects a new client, authenticating if `config` has credentials,
+    /// and turns it into a subscriber.
+    pub async fn connect(config: ClientConfig) -> anyhow::Result<Self> {
+        let mut client = Client::with_config(config);
+        client.connect().await?;
+        Ok(client.into_pubsub())
+    }
+
+    pub async fn subscribe<C: AsRef<str>>(&mut self, channels: &[C]) -> anyhow::Result<()> {
+        self.change("SUBSCRIBE", channels).await
+    }
+
+    /// Unsubscribes from `channels`, or from every channel if empty.
+    pub async fn unsubscribe<C: AsRef<str>>(&mut self, channels: &[C]) -> anyhow::Result<()> {
+        self.change("UNSUBSCRIBE", channels).await
+    }
+
+    /// Subscribes to every channel matching each glob style pattern.
+    pub async fn psubscribe<P: AsRef<str>>(&mut self, patterns: &[P]) -> anyhow::Result<()> {
+        self.change("PSUBSCRIBE", patterns).await
+    }
+
+    /// Unsubscribes from `patterns`, or from every pattern if empty.
+    pub async fn punsubscribe<P: AsRef<str>>(&mut self, patterns: &[P]) -> anyhow::Result<()> {
+        self.change("PUNSUBSCRIBE", patterns).await
+    }
+
+    /// The channels currently subscribed to.
+    pub fn channels(&self) -> impl Iterator<Item = &str> {
+        self.channels.iter().map(String::as_str)
+    }
+
+    /// The patterns currently subscribed to.
+    pub fn patterns(&self) -> impl Iterator<Item = &str> {
+        self.patterns.iter().map(String::as_str)
+    }
+
+    /// Waits for the next message on any subscription.
+    pub async fn next_message(&mut self) -> anyhow::Result<Message> {
+        if let Some(msg) = self.pending.pop_front() {
+            return Ok(msg);
+        }
+        loop {
+            if let Event::Message(msg) = self.read_event().await? {
+                return Ok(msg);
+            }
+        }
+    }
+
+    /// Yields every message received until the connection fails, ending
+    /// after the first error.
+    pub fn messages(&mut self) -> impl Stream<Item = anyhow::Result<Message>> + '_ {
+        stream::unfold(Some(self), |state| async move {
+            let pubsub = state?;
+            match pubsub.next_message().await {
+                Ok(msg) => Some((Ok(msg), Some(pubsub))),
+                Err(e) => Some((Err(e), None)),
+            }
+        })
+    }
+
+    /// sends a (un)subscribe and waits for the server to confirm every
+    /// channel or pattern in it, queueing messages that arrive meanwhile
+    async fn change<C: AsRef<str>>(&mut self, command: &str, names: &[C]) -> anyhow::Result<()> {
+        let mut enc = self.client.encoder();
+        enc.add_arr(1 + names.len()).add_bulk(command);
+        for name in names {
+            enc.add_bulk(name.as_ref());
+        }
+        self.client.send().await?;
+
+        let kind = command.to_ascii_lowercase();
+        let tracked = match kind.as_str() {
+            "subscribe" | "unsubscribe" => &self.channels,
+            _ => &self.patterns,
+        };
+        // unsubscribing from everything confirms each subscription, or
+        // once with no name if there were none
+        let mut remaining = match names.len() {
+            0 => tracked.len().max(1),
+            n => n,
+        };
+        while remaining > 0 {
+            match self.read_event().await? {
+                Event::Message(msg) => self.pending.push_back(msg),
+                Event::Confirm { kind: k, name } if k == kind => {
+                    remaining -= 1;
+                    let Some(name) = name else {
+                        continue;
+                    };
+                    match kind.as_str() {
+                        "subscribe" => self.channels.insert(name),
+                        "unsubscribe" => self.channels.remove(&name),
+                        "psubscribe" => self.patterns.insert(name),
+                        _ => self.patterns.remove(&name),
+                    };
+                }
+                Event::Confirm { .. } => {}
+            }
+        }
+        Ok(())
+    }
+
+    async fn read_event(&mut self) -> anyhow::Result<Event> {
+        let data = self.client.read_any_frame().await?;
+        let items = match data {
+            LexiData::Push(items) | LexiData::Array(items) => items,
+            LexiData::Error(e) => return Err(Error::Server(e).into()),
+            other => return Err(unexpected("pub/sub frame", other)),
+        };
+        let mut items = items.into_iter();
+        let kind = match items.next() {
+            Some(LexiData::Bulk(kind)) => kind.to_ascii_lowercase(),
+            Some(other) => return Err(unexpected("pub/sub frame kind", other)),
+            None => return Err(anyhow::anyhow!("empty pub/sub frame")),
+        };
+        let mut next = |what: &str| -> anyhow::Result<LexiData> {
+            items
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("{} frame without a {}", kind, what))
+        };
+        let event = match kind.as_str() {
+            "message" => Event::Message(Message {
+                channel: String::from_lexi_data(next("channel")?)?,
+                pattern: None,
+                payload: next("payload")?,
+            }),
+            "pmessage" => Event::Message(Message {
+                pattern: Some(String::from_lexi_data(next("pattern")?)?),
+                channel: String::from_lexi_data(next("channel")?)?,
+                payload: next("payload")?,
+            }),
+            _ => Event::Confirm {
+                name: Option::from_lexi_data(next("name")?)?,
+                kind,
+            },
+        };
+        Ok(event)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::{StreamExt, TryStreamExt};
+
+    use super::Message;
+    use crate::{client::Client, lexi_data::LexiData, stub::Stub};
+
+    #[tokio::test]
+    async fn it_subscribes_and_streams_messages() -> anyhow::Result<()> {
+        let stub = Stub::serve(vec![
+            (
+                b"*3\r\n$9\r\nSUBSCRIBE\r\n$1\r\na\r\n$1\r\nb\r\n",
+                b">3\r\n$9\r\nsubscribe\r\n$1\r\na\r\n:1\r\n\
+                  >3\r\n$9\r\nsubscribe\r\n$1\r\nb\r\n:2\r\n",
+            ),
+            (
+                b"*2\r\n$10\r\nPSUBSCRIBE\r\n$2\r\nc*\r\n",
+                b">3\r\n$7\r\nmessage\r\n$1\r\na\r\n$2\r\nhi\r\n\
+                  >3\r\n$10\r\npsubscribe\r\n$2\r\nc*\r\n:3\r\n\
+                  >4\r\n$8\r\npmessage\r\n$2\r\nc*\r\n$2\r\ncd\r\n:5\r\n",
+            ),
+            (
+                b"*1\r\n$11\r\nUNSUBSCRIBE\r\n",
+                b">3\r\n$11\r\nunsubscribe\r\n$1\r\na\r\n:2\r\n\
+                  >3\r\n$11\r\nunsubscribe\r\n$1\r\nb\r\n:1\r\n",
+            ),
+        ])
+        .await?;
+        let mut client = Client::new(stub.addr())?;
+        client.connect().await?;
+        let mut pubsub = client.into_pubsub();
+        pubsub.subscribe(&["a", "b"]).await?;
+        pubsub.psubscribe(&["c*"]).await?;
+        assert_eq!(pubsub.patterns().collect::<Vec<_>>(), vec!["c*"]);
+
+        let messages: Vec<Message> = pubsub.messages().take(2).try_collect().await?;
+        assert_eq!((messages[0].channel(), messages[0].pattern()), ("a", None));
+        assert_eq!(messages[0].clone().into_payload::<String>()?, "hi");
+        assert_eq!(
+            (messages[1].channel(), messages[1].pattern()),
+            ("cd", Some("c*"))
+        );
+        assert_eq!(messages[1].payload(), &LexiData::Int(5));
+
+        pubsub.unsubscribe::<&str>(&[]).await?;
+        assert_eq!(pubsub.channels().count(), 0);
+        stub.finish().await
+    }
+}
@@ -0,0 +1,411 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use futures::{
+    future::{join_all, BoxFuture},
+    FutureExt,
+};
+use tokio::task::JoinHandle;
+
+use crate::{
+    client::{Client, ClientConfig},
+    error::Error,
+    lexi_data::{FromLexiData, LexiData, SimpleString, TryIntoLexiData},
+};
+
+/// How often reads check on down replicas unless told otherwise.
+pub const DEFAULT_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long a replica has to answer a read unless told otherwise.
+pub const DEFAULT_REPLICA_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long a replica has to answer a health check `PING`.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Where `ReplicatedClient` sends reads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReadPreference {
+    /// Read from the primary only, so reads always see the latest writes.
+    #[default]
+    Primary,
+    /// Spread reads over healthy replicas, reading from the primary when
+    /// none are left. Reads may lag behind writes.
+    PreferReplica,
+}
+
+/// Sends writes to a primary and, with `ReadPreference::PreferReplica`,
+/// reads to its replicas.
+///
+/// Replicas that fail to connect, drop a read or take longer than the
+/// replica timeout to answer it are marked down, and the read is retried
+/// on the next replica or the primary. Once every health
+/// check interval, reads start reconnecting and `PING`ing down replicas in
+/// the background rather than waiting on them. `check_health` checks every
+/// replica at once and waits for the results.
+///
+/// ```ignore
+/// let mut client = ReplicatedClient::new("127.0.0.1:5173", &["127.0.0.1:5174"])?
+///     .read_preference(ReadPreference::PreferReplica);
+/// client.connect().await?;
+/// client.set("a", 1).await?;
+/// let a: Option<i64> = client.get("a").await?;
+/// ```
+pub struct ReplicatedClient {
+    primary: Client,
+    replicas: Vec<Replica>,
+    read_preference: ReadPreference,
+    health_check_interval: Duration,
+    replica_timeout: Duration,
+    last_health_check: Option<Instant>,
+    /// the replica the next read starts at, rotating reads among them
+    next: usize,
+}
+
+struct Replica {
+    client: Client,
+    healthy: bool,
+    /// a background reconnect, which has the real client meanwhile
+    recovering: Option<JoinHandle<(Client, bool)>>,
+}
+
+impl ReplicatedClient {
+    pub fn new(primary: &str, replicas: &[&str]) -> anyhow::Result<Self> {
+        let replicas = replicas
+            .iter()
+            .map(|addr| ClientConfig::new(addr))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self::with_configs(ClientConfig::new(primary)?, replicas))
+    }
+
+    pub fn with_configs(primary: ClientConfig, replicas: Vec<ClientConfig>) -> Self {
+        Self {
+            primary: Client::with_config(primary),
+            replicas: replicas
+                .into_iter()
+                .map(|config| Replica {
+                    client: Client::with_config(config),
+                    healthy: false,
+                    recovering: None,
+                })
+                .collect(),
+            read_preference: ReadPreference::default(),
+            health_check_interval: DEFAULT_HEALTH_CHECK_INTERVAL,
+            replica_timeout: DEFAULT_REPLICA_TIMEOUT,
+            last_health_check: None,
+            next: 0,
+        }
+    }
+
+    pub fn read_preference(mut self, read_preference: ReadPreference) -> Self {
+        self.read_preference = read_preference;
+        self
+    }
+
+    /// How long reads go between checks on down replicas.
+    pub fn health_check_interval(mut self, interval: Duration) -> Self {
+        self.health_check_interval = interval;
+        self
+    }
+
+    /// How long a read waits on a replica before trying elsewhere.
+    pub fn replica_timeout(mut self, timeout: Duration) -> Self {
+        self.replica_timeout = timeout;
+        self
+    }
+
+    /// Connects to the primary, failing if it is unreachable, and to every
+    /// replica that is reachable.
+    pub async fn connect(&mut self) -> anyhow::Result<()> {
+        self.primary.connect().await?;
+        self.check_health().await;
+        Ok(())
+    }
+
+    /// Reconnects to replicas that are down and `PING`s every replica,
+    /// all at once, returning how many are up.
+    pub async fn check_health(&mut self) -> usize {
+        join_all(self.replicas.iter_mut().map(Replica::check)).await;
+        self.last_health_check = Some(Instant::now());
+        self.healthy_replicas()
+    }
+
+    /// How many replicas reads can currently go to.
+    pub fn healthy_replicas(&self) -> usize {
+        self.replicas.iter().filter(|r| r.healthy).count()
+    }
+
+    /// The primary, for writes without a helper here.
+    pub fn primary(&mut self) -> &mut Client {
+        &mut self.primary
+    }
+
+    /// Runs a read according to the read preference, moving on to the next
+    /// replica or the primary if a replica's connection fails or it does
+    /// not answer within the replica timeout. Errors the server answers
+    /// with are returned as they are.
+    ///
+    /// ```ignore
+    /// let len = client.read(|c| Box::pin(c.llen("list"))).await?;
+    /// ```
+    pub async fn read<T, F>(&mut self, mut read: F) -> anyhow::Result<T>
+    where
+        F: for<'c> FnMut(&'c mut Client) -> BoxFuture<'c, anyhow::Result<T>>,
+    {
+        if self.read_preference == ReadPreference::PreferReplica && !self.replicas.is_empty() {
+            let due = match self.last_health_check {
+                Some(at) => at.elapsed() >= self.health_check_interval,
+                None => true,
+            };
+            for replica in &mut self.replicas {
+                replica.reap();
+                if due {
+                    replica.recover();
+                }
+            }
+            if due {
+                self.last_health_check = Some(Instant::now());
+            }
+            for _ in 0..self.replicas.len() {
+                let i = self.next % self.replicas.len();
+                self.next = self.next.wrapping_add(1);
+                let replica = &mut self.replicas[i];
+                if !replica.healthy {
+                    continue;
+                }
+                match tokio::time::timeout(self.replica_timeout, read(&mut replica.client)).await {
+                    Ok(Err(e)) if is_connection_error(&e) => replica.healthy = false,
+                    Ok(res) => return res,
+                    // the reply may still come, so the client reconnects
+                    // before its next command rather than read it then
+                    Err(_) => replica.healthy = false,
+                }
+            }
+        }
+        read(&mut self.primary).await
+    }
+
+    pub async fn set(
+        &mut self,
+        key: impl Into<LexiData>,
+        value: impl TryIntoLexiData,
+    ) -> anyhow::Result<LexiData> {
+        self.primary.set(key, value).await
+    }
+
+    pub async fn del(&mut self, key: impl Into<LexiData>) -> anyhow::Result<LexiData> {
+        self.primary.del(key).await
+    }
+
+    pub async fn push(&mut self, value: impl TryIntoLexiData) -> anyhow::Result<LexiData> {
+        self.primary.push(value).await
+    }
+
+    pub async fn get<T: FromLexiData>(&mut self, key: impl Into<LexiData>) -> anyhow::Result<T> {
+        let key = key.into();
+        // read the reply as it is, so `T` need not outlive every borrow
+        let data = self
+            .read(|client| Box::pin(client.get::<LexiData>(key.clone())))
+            .await?;
+        T::from_lexi_data(data)
+    }
+
+    pub async fn zhas(&mut self, value: impl Into<LexiData>) -> anyhow::Result<LexiData> {
+        let value = value.into();
+        self.read(|client| Box::pin(client.zhas(value.clone())))
+            .await
+    }
+
+    pub async fn keys(&mut self) -> anyhow::Result<LexiData> {
+        self.read(|client| Box::pin(client.keys())).await
+    }
+}
+
+impl Replica {
+    async fn check(&mut self) {
+        if let Some(recovering) = self.recovering.take() {
+            if let Ok((client, healthy)) = recovering.await {
+                self.client = client;
+                self.healthy = healthy;
+                return;
+            }
+        }
+        let reconnect = !self.healthy || !self.client.is_connected();
+        self.healthy = probe(&mut self.client, reconnect).await;
+    }
+
+    /// starts reconnecting a down replica on its own task
+    fn recover(&mut self) {
+        if self.healthy || self.recovering.is_some() {
+            return;
+        }
+        let idle = Client::with_config(self.client.config().clone());
+        let mut client = std::mem::replace(&mut self.client, idle);
+        self.recovering = Some(tokio::spawn(async move {
+            let healthy = probe(&mut client, true).await;
+            (client, healthy)
+        }));
+    }
+
+    /// takes back the client of a finished background reconnect
+    fn reap(&mut self) {
+        if !matches!(&self.recovering, Some(recovering) if recovering.is_finished()) {
+            return;
+        }
+        let done = self.recovering.take().and_then(FutureExt::now_or_never);
+        if let Some(Ok((client, healthy))) = done {
+            self.client = client;
+            self.healthy = healthy;
+        }
+    }
+}
+
+/// `PING`s `client`, reconnecting it first if asked, with each step
+/// limited to `HEALTH_CHECK_TIMEOUT`
+async fn probe(client: &mut Client, reconnect: bool) -> bool {
+    if reconnect {
+        match tokio::time::timeout(HEALTH_CHECK_TIMEOUT, client.connect()).await {
+            Ok(Ok(())) => {}
+            _ => return false,
+        }
+    }
+    matches!(
+        tokio::time::timeout(HEALTH_CHECK_TIMEOUT, client.ping()).await,
+        Ok(Ok(LexiData::Simple(SimpleString::Pong)))
+    )
+}
+
+/// whether a read failed because of the connection rather than the reply
+fn is_connection_error(e: &anyhow::Error) -> bool {
+    e.is::<io::Error>()
+        || matches!(
+            e.downcast_ref::<Error>(),
+            Some(Error::NotConnected | Error::ConnectionClosed)
+        )
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{ReadPreference, ReplicatedClient};
+    use crate::{error::Error, stub::Stub};
+
+    const PING: (&[u8], &[u8]) = (b"+PING\r\n", b"+PONG\r\n");
+    const GET_A: &[u8] = b"*2\r\n$3\r\nGET\r\n$1\r\na\r\n";
+
+    #[tokio::test]
+    async fn it_fails_reads_over_to_the_primary() -> anyhow::Result<()> {
+        let primary = Stub::serve(vec![
+            (b"*3\r\n$3\r\nSET\r\n$1\r\na\r\n:1\r\n", b"+OK\r\n"),
+            (b"*2\r\n$3\r\nGET\r\n$1\r\na\r\n", b":1\r\n"),
+        ])
+        .await?;
+        let replica = Stub::serve(vec![
+            (b"+PING\r\n", b"+PONG\r\n"),
+            (b"*2\r\n$3\r\nGET\r\n$1\r\na\r\n", b":1\r\n"),
+        ])
+        .await?;
+        let mut client = ReplicatedClient::new(primary.addr(), &[replica.addr()])?
+            .read_preference(ReadPreference::PreferReplica);
+        client.connect().await?;
+        assert_eq!(client.healthy_replicas(), 1);
+
+        assert!(client.set("a", 1).await?.is_ok());
+        assert_eq!(client.get::<i64>("a").await?, 1);
+        replica.finish().await?;
+        assert_eq!(client.get::<i64>("a").await?, 1);
+        assert_eq!(client.healthy_replicas(), 0);
+        assert_eq!(client.check_health().await, 0);
+        primary.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_reads_from_the_primary_by_default() -> anyhow::Result<()> {
+        let primary = Stub::serve(vec![(GET_A, b"+NONE\r\n")]).await?;
+        let mut client = ReplicatedClient::new(primary.addr(), &["127.0.0.1:1"])?;
+        client.connect().await?;
+        assert_eq!(client.get::<Option<i64>>("a").await?, None);
+        primary.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_rotates_reads_over_the_replicas() -> anyhow::Result<()> {
+        let primary = Stub::serve(vec![]).await?;
+        let mut replicas = Vec::new();
+        for _ in 0..3 {
+            replicas.push(Stub::serve(vec![PING, (GET_A, b":1\r\n")]).await?);
+        }
+        let addrs: Vec<&str> = replicas.iter().map(Stub::addr).collect();
+        let mut client = ReplicatedClient::new(primary.addr(), &addrs)?
+            .read_preference(ReadPreference::PreferReplica);
+        client.connect().await?;
+        assert_eq!(client.healthy_replicas(), 3);
+        for _ in 0..3 {
+            assert_eq!(client.get::<i64>("a").await?, 1);
+        }
+        // each replica served one read, so none dropped a connection
+        assert_eq!(client.healthy_replicas(), 3);
+        for replica in replicas {
+            replica.finish().await?;
+        }
+        primary.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_marks_recovered_replicas_healthy() -> anyhow::Result<()> {
+        let primary = Stub::serve(vec![(GET_A, b":1\r\n")]).await?;
+        // nothing listens here until the replica comes back
+        let addr = Stub::bind().await?.addr().to_owned();
+        let mut client = ReplicatedClient::new(primary.addr(), &[&addr])?
+            .read_preference(ReadPreference::PreferReplica)
+            .health_check_interval(Duration::ZERO);
+        client.connect().await?;
+        assert_eq!(client.healthy_replicas(), 0);
+
+        let replica = Stub::bind_to(&addr)
+            .await?
+            .play(vec![PING, (GET_A, b":2\r\n")]);
+        // the replica reconnects in the background while the primary reads
+        assert_eq!(client.get::<i64>("a").await?, 1);
+        assert_eq!(client.check_health().await, 1);
+        assert_eq!(client.get::<i64>("a").await?, 2);
+        replica.finish().await?;
+        primary.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_does_not_fail_over_on_server_errors() -> anyhow::Result<()> {
+        let primary = Stub::serve(vec![]).await?;
+        let replica = Stub::serve(vec![PING, (GET_A, b"-wrong type\r\n")]).await?;
+        let mut client = ReplicatedClient::new(primary.addr(), &[replica.addr()])?
+            .read_preference(ReadPreference::PreferReplica);
+        client.connect().await?;
+        let err = client.get::<i64>("a").await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::Server("wrong type".to_owned()))
+        );
+        assert_eq!(client.healthy_replicas(), 1);
+        replica.finish().await?;
+        primary.finish().await
+    }
+
+    #[tokio::test]
+    async fn it_fails_over_from_replicas_that_do_not_answer() -> anyhow::Result<()> {
+        let primary = Stub::serve(vec![(GET_A, b":1\r\n")]).await?;
+        // waiting on a second connection keeps the first one open, unanswered
+        let replica = Stub::bind()
+            .await?
+            .play_each(vec![vec![PING, (GET_A, b"")], vec![]]);
+        let mut client = ReplicatedClient::new(primary.addr(), &[replica.addr()])?
+            .read_preference(ReadPreference::PreferReplica)
+            .replica_timeout(Duration::from_millis(50));
+        client.connect().await?;
+        assert_eq!(client.healthy_replicas(), 1);
+        assert_eq!(client.get::<i64>("a").await?, 1);
+        assert_eq!(client.healthy_replicas(), 0);
+        primary.finish().await
+    }
+}
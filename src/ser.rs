@@ -0,0 +1,365 @@
+use serde::{
+    ser::{self, Impossible, SerializeMap, SerializeStruct},
+    Serialize, Serializer,
+};
+
+use crate::{de::Error, lexi_data::LexiData};
+
+/// Serializes a struct with named fields, or a map, into the field value
+/// pairs stored in a hash.
+///
+/// Field values must be strings, numbers, bools or chars. Fields holding
+/// `None` are left out; `Client::hset_struct` deletes them from the hash so
+/// they read back as `None`.
+pub fn to_fields<T: Serialize + ?Sized>(value: &T) -> Result<Vec<(String, LexiData)>, Error> {
+    Ok(to_optional_fields(value)?
+        .into_iter()
+        .filter_map(|(field, value)| Some((field, value?)))
+        .collect())
+}
+
+/// like `to_fields`, but keeps the fields holding `None`
+pub(crate) fn to_optional_fields<T: Serialize + ?Sized>(
+    value: &T,
+) -> Result<Vec<(String, Option<LexiData>)>, Error> {
+    value.serialize(FieldsSerializer)
+}
+
+struct FieldsSerializer;
+
+struct Fields {
+    fields: Vec<(String, Option<LexiData>)>,
+    key: Option<String>,
+}
+
+impl Fields {
+    fn push<T: Serialize + ?Sized>(&mut self, field: String, value: &T) -> Result<(), Error> {
+        let value = value.serialize(ValueSerializer)?;
+        self.fields.push((field, value));
+        Ok(())
+    }
+}
+
+fn unsupported(what: &str) -> Error {
+    ser::Error::custom(format!("{} can not be stored as hash fields", what))
+}
+
+macro_rules! unsupported_fields {
+    ($($method:ident($($arg:ty),*) -> $ret:ty;)*) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<$ret, Error> {
+                Err(unsupported(stringify!($method)))
+            }
+        )*
+    };
+}
+
+impl Serializer for FieldsSerializer {
+    type Ok = Vec<(String, Option<LexiData>)>;
+    type Error = Error;
+    type SerializeSeq = Impossible<Self::Ok, Error>;
+    type SerializeTuple = Impossible<Self::Ok, Error>;
+    type SerializeTupleStruct = Impossible<Self::Ok, Error>;
+    type SerializeTupleVariant = Impossible<Self::Ok, Error>;
+    type SerializeMap = Fields;
+    type SerializeStruct = Fields;
+    type SerializeStructVariant = Impossible<Self::Ok, Error>;
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Fields, Error> {
+        Ok(Fields {
+            fields: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Fields, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, Error> {
+        Err(unsupported("enum"))
+    }
+
+    unsupported_fields! {
+        serialize_bool(bool) -> Self::Ok;
+        serialize_i8(i8) -> Self::Ok;
+        serialize_i16(i16) -> Self::Ok;
+        serialize_i32(i32) -> Self::Ok;
+        serialize_i64(i64) -> Self::Ok;
+        serialize_u8(u8) -> Self::Ok;
+        serialize_u16(u16) -> Self::Ok;
+        serialize_u32(u32) -> Self::Ok;
+        serialize_u64(u64) -> Self::Ok;
+        serialize_f32(f32) -> Self::Ok;
+        serialize_f64(f64) -> Self::Ok;
+        serialize_char(char) -> Self::Ok;
+        serialize_str(&str) -> Self::Ok;
+        serialize_bytes(&[u8]) -> Self::Ok;
+        serialize_none() -> Self::Ok;
+        serialize_unit() -> Self::Ok;
+        serialize_unit_struct(&'static str) -> Self::Ok;
+        serialize_unit_variant(&'static str, u32, &'static str) -> Self::Ok;
+        serialize_seq(Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple(usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+        serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant;
+        serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant;
+    }
+}
+
+impl SerializeStruct for Fields {
+    type Ok = Vec<(String, Option<LexiData>)>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.push(key.to_owned(), value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        Ok(self.fields)
+    }
+}
+
+impl SerializeMap for Fields {
+    type Ok = Vec<(String, Option<LexiData>)>;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = match key.serialize(ValueSerializer)? {
+            Some(LexiData::Bulk(s)) => Some(s),
+            Some(LexiData::Int(i)) => Some(i.to_string()),
+            _ => return Err(unsupported("non string key")),
+        };
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        match self.key.take() {
+            Some(key) => self.push(key, value),
+            None => Err(ser::Error::custom("value serialized before its key")),
+        }
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        Ok(self.fields)
+    }
+}
+
+/// serializes a single field value, returning `None` for fields to skip
+struct ValueSerializer;
+
+impl Serializer for ValueSerializer {
+    type Ok = Option<LexiData>;
+    type Error = Error;
+    type SerializeSeq = Impossible<Self::Ok, Error>;
+    type SerializeTuple = Impossible<Self::Ok, Error>;
+    type SerializeTupleStruct = Impossible<Self::Ok, Error>;
+    type SerializeTupleVariant = Impossible<Self::Ok, Error>;
+    type SerializeMap = Impossible<Self::Ok, Error>;
+    type SerializeStruct = Impossible<Self::Ok, Error>;
+    type SerializeStructVariant = Impossible<Self::Ok, Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Error> {
+        Ok(Some(v.into()))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Error> {
+        Ok(Some(v.into()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Error> {
+        Ok(Some(v.into()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Error> {
+        Ok(Some(v.into()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Error> {
+        Ok(Some(v.into()))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Error> {
+        Ok(Some(v.into()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Error> {
+        Ok(Some(v.into()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Error> {
+        Ok(Some(v.into()))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Error> {
+        LexiData::try_from(v)
+            .map(Some)
+            .map_err(|_| ser::Error::custom(format!("{} does not fit in an i64", v)))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Error> {
+        Ok(Some(v.into()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Error> {
+        Ok(Some(v.into()))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Error> {
+        Ok(Some(v.into()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Error> {
+        Ok(Some(v.into()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Error> {
+        match std::str::from_utf8(v) {
+            Ok(s) => Ok(Some(s.into())),
+            Err(_) => Err(unsupported("non utf-8 bytes")),
+        }
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Error> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Error> {
+        Ok(Some(variant.into()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, Error> {
+        Err(unsupported("enum with data"))
+    }
+
+    unsupported_fields! {
+        serialize_seq(Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple(usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+        serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant;
+        serialize_map(Option<usize>) -> Self::SerializeMap;
+        serialize_struct(&'static str, usize) -> Self::SerializeStruct;
+        serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use serde::Serialize;
+
+    use super::to_fields;
+    use crate::lexi_data::LexiData;
+
+    #[derive(Serialize)]
+    enum Role {
+        Admin,
+    }
+
+    #[derive(Serialize)]
+    struct User {
+        name: String,
+        age: u8,
+        score: f64,
+        active: bool,
+        role: Role,
+        nickname: Option<String>,
+    }
+
+    #[test]
+    fn it_serializes_structs_to_fields() -> anyhow::Result<()> {
+        let user = User {
+            name: "vince".to_owned(),
+            age: 24,
+            score: 1.5,
+            active: true,
+            role: Role::Admin,
+            nickname: None,
+        };
+        assert_eq!(
+            to_fields(&user)?,
+            vec![
+                ("name".to_owned(), "vince".into()),
+                ("age".to_owned(), LexiData::Int(24)),
+                ("score".to_owned(), LexiData::Double(1.5)),
+                ("active".to_owned(), LexiData::Int(1)),
+                ("role".to_owned(), "Admin".into()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_serializes_maps_to_fields() -> anyhow::Result<()> {
+        let map = BTreeMap::from([("a", 1), ("b", 2)]);
+        assert_eq!(
+            to_fields(&map)?,
+            vec![
+                ("a".to_owned(), LexiData::Int(1)),
+                ("b".to_owned(), LexiData::Int(2)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_values_that_are_not_fields() {
+        assert!(to_fields(&5).is_err());
+        assert!(to_fields(&BTreeMap::from([("a", vec![1])])).is_err());
+        assert!(to_fields(&BTreeMap::from([("a", u64::MAX)])).is_err());
+    }
+}
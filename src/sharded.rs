@@ -0,0 +1,406 @@
+use std::collections::{BTreeMap, HashMap};
+
+use futures::future::try_join_all;
+
+use crate::{
+    client::{Client, ClientConfig},
+    error::Error,
+    lexi_data::{FromLexiData, LexiData, TryIntoLexiData},
+};
+
+/// How many points each node gets on the ring unless told otherwise.
+pub const DEFAULT_VIRTUAL_NODES: usize = 160;
+
+/// Spreads keys over independent lexidb nodes.
+///
+/// Keys are placed on a consistent hash ring where every node owns many
+/// virtual points, so adding or removing a node only moves the keys that
+/// node gains or loses; no data is migrated. Commands without a helper here
+/// can be sent to the right node through `shard`:
+///
+/// ```ignore
+/// let mut sharded = ShardedClient::new(&["127.0.0.1:5173", "127.0.0.1:5174"])?;
+/// sharded.connect().await?;
+/// sharded.set("a", 1).await?;
+/// let len = sharded.shard("list")?.llen("list").await?;
+/// ```
+pub struct ShardedClient {
+    ring: HashRing,
+    nodes: HashMap<String, Client>,
+}
+
+impl ShardedClient {
+    pub fn new(addresses: &[&str]) -> anyhow::Result<Self> {
+        let configs = addresses
+            .iter()
+            .map(|addr| ClientConfig::new(addr))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self::with_configs(configs))
+    }
+
+    /// One node per config, for nodes that need credentials or caching.
+    /// Configs for an address already given are skipped.
+    pub fn with_configs(configs: Vec<ClientConfig>) -> Self {
+        let mut sharded = Self {
+            ring: HashRing::new(DEFAULT_VIRTUAL_NODES),
+            nodes: HashMap::new(),
+        };
+        for config in configs {
+            sharded.insert(Client::with_config(config));
+        }
+        sharded
+    }
+
+    /// Places every node on the ring `count` times. More points even out
+    /// how many keys each node gets, at the cost of a larger ring.
+    pub fn virtual_nodes(mut self, count: usize) -> Self {
+        self.ring = HashRing::new(count);
+        for node in self.nodes.keys() {
+            self.ring.add(node);
+        }
+        self
+    }
+
+    /// Connects to every node.
+    pub async fn connect(&mut self) -> anyhow::Result<()> {
+        try_join_all(self.nodes.values_mut().map(Client::connect)).await?;
+        Ok(())
+    }
+
+    /// Connects to a new node and starts routing its share of keys to it.
+    /// Fails with `Error::DuplicateNode` if the address is already a node.
+    pub async fn add_node(&mut self, config: ClientConfig) -> anyhow::Result<()> {
+        let node = config.addr().to_string();
+        if self.nodes.contains_key(&node) {
+            return Err(Error::DuplicateNode(node).into());
+        }
+        let mut client = Client::with_config(config);
+        client.connect().await?;
+        self.insert(client);
+        Ok(())
+    }
+
+    /// Stops routing keys to the node at `address`, handing back its client.
+    /// Its keys move to the nodes that follow it on the ring.
+    pub fn remove_node(&mut self, address: &str) -> Option<Client> {
+        let client = self.nodes.remove(address)?;
+        self.ring.remove(address);
+        Some(client)
+    }
+
+    /// The addresses of every node.
+    pub fn nodes(&self) -> impl Iterator<Item = &str> {
+        self.nodes.keys().map(String::as_str)
+    }
+
+    /// The address of the node `key` lives on, failing with
+    /// `Error::NoNodes` if there are none.
+    pub fn node_for(&self, key: impl Into<LexiData>) -> anyhow::Result<&str> {
+        Ok(self
+            .ring
+            .node_for(&key_bytes(&key.into()))
+            .ok_or(Error::NoNodes)?)
+    }
+
+    /// The client of the node `key` lives on, failing with `Error::NoNodes`
+    /// if there are none.
+    pub fn shard(&mut self, key: impl Into<LexiData>) -> anyhow::Result<&mut Client> {
+        let node = self
+            .ring
+            .node_for(&key_bytes(&key.into()))
+            .ok_or(Error::NoNodes)?;
+        Ok(self.nodes.get_mut(node).expect("ring node has a client"))
+    }
+
+    pub async fn set(
+        &mut self,
+        key: impl Into<LexiData>,
+        value: impl TryIntoLexiData,
+    ) -> anyhow::Result<LexiData> {
+        let key = key.into();
+        self.shard(key.clone())?.set(key, value).await
+    }
+
+    pub async fn get<T: FromLexiData>(&mut self, key: impl Into<LexiData>) -> anyhow::Result<T> {
+        let key = key.into();
+        self.shard(key.clone())?.get(key).await
+    }
+
+    pub async fn del(&mut self, key: impl Into<LexiData>) -> anyhow::Result<LexiData> {
+        let key = key.into();
+        self.shard(key.clone())?.del(key).await
+    }
+
+    pub async fn incr(&mut self, key: impl Into<LexiData>) -> anyhow::Result<i64> {
+        let key = key.into();
+        self.shard(key.clone())?.incr(key).await
+    }
+
+    /// Sets every pair with one `mset` per node, run concurrently.
+    pub async fn mset<K, V>(&mut self, pairs: &[(K, V)]) -> anyhow::Result<()>
+    where
+        K: Into<LexiData> + Clone,
+        V: TryIntoLexiData + Clone,
+    {
+        let pairs = pairs
+            .iter()
+            .map(|(key, value)| Ok((key.clone().into(), value.clone().try_into_lexi_data()?)))
+            .collect::<anyhow::Result<Vec<(LexiData, LexiData)>>>()?;
+        let groups = self.group(pairs.iter().map(|(key, _)| key.clone()))?;
+        let batches = self.nodes.iter_mut().filter_map(|(node, client)| {
+            let batch: Vec<(LexiData, LexiData)> = groups
+                .get(node)?
+                .iter()
+                .map(|&i| pairs[i].clone())
+                .collect();
+            Some(async move { client.mset(&batch).await })
+        });
+        try_join_all(batches).await?;
+        Ok(())
+    }
+
+    /// Gets every key with one `mget` per node, run concurrently. Values
+    /// come back in the order of `keys`.
+    pub async fn mget<K, T>(&mut self, keys: &[K]) -> anyhow::Result<Vec<Option<T>>>
+    where
+        K: Into<LexiData> + Clone,
+        T: FromLexiData,
+    {
+        let groups = self.group(keys.iter().map(|key| key.clone().into()))?;
+        let batches = self.nodes.iter_mut().filter_map(|(node, client)| {
+            let indices = groups.get(node)?;
+            let batch: Vec<LexiData> = indices.iter().map(|&i| keys[i].clone().into()).collect();
+            Some(async move {
+                let values = client.mget::<_, T>(&batch).await?;
+                anyhow::Ok(indices.iter().copied().zip(values))
+            })
+        });
+        let mut res: Vec<Option<T>> = keys.iter().map(|_| None).collect();
+        for values in try_join_all(batches).await? {
+            for (i, value) in values {
+                res[i] = value;
+            }
+        }
+        Ok(res)
+    }
+
+    /// Deletes every key with one `mdel` per node, run concurrently,
+    /// returning how many keys were deleted in total.
+    pub async fn mdel<K>(&mut self, keys: &[K]) -> anyhow::Result<usize>
+    where
+        K: Into<LexiData> + Clone,
+    {
+        let groups = self.group(keys.iter().map(|key| key.clone().into()))?;
+        let batches = self.nodes.iter_mut().filter_map(|(node, client)| {
+            let batch: Vec<LexiData> = groups
+                .get(node)?
+                .iter()
+                .map(|&i| keys[i].clone().into())
+                .collect();
+            Some(async move { client.mdel(&batch).await })
+        });
+        Ok(try_join_all(batches).await?.into_iter().sum())
+    }
+
+    /// adds `client` to the ring unless its address already is a node
+    fn insert(&mut self, client: Client) {
+        let node = client.config().addr().to_string();
+        if self.nodes.contains_key(&node) {
+            return;
+        }
+        self.ring.add(&node);
+        self.nodes.insert(node, client);
+    }
+
+    /// indices of `keys` by the node they live on
+    fn group(
+        &self,
+        keys: impl Iterator<Item = LexiData>,
+    ) -> anyhow::Result<HashMap<String, Vec<usize>>> {
+        let mut groups: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, key) in keys.enumerate() {
+            let node = self.node_for(key)?.to_owned();
+            groups.entry(node).or_default().push(i);
+        }
+        Ok(groups)
+    }
+}
+
+/// the bytes a key is hashed by, so `"5"` and `5` land on the same node
+fn key_bytes(key: &LexiData) -> Vec<u8> {
+    match key {
+        LexiData::Bulk(s) => s.as_bytes().to_vec(),
+        LexiData::Int(i) => i.to_string().into_bytes(),
+        LexiData::Double(d) => d.to_string().into_bytes(),
+        other => other.to_string().into_bytes(),
+    }
+}
+
+/// a consistent hash ring mapping points to node names
+struct HashRing {
+    virtual_nodes: usize,
+    points: BTreeMap<u64, String>,
+}
+
+impl HashRing {
+    fn new(virtual_nodes: usize) -> Self {
+        Self {
+            virtual_nodes: virtual_nodes.max(1),
+            points: BTreeMap::new(),
+        }
+    }
+
+    fn add(&mut self, node: &str) {
+        for i in 0..self.virtual_nodes {
+            self.points
+                .insert(hash(format!("{}#{}", node, i).as_bytes()), node.to_owned());
+        }
+    }
+
+    fn remove(&mut self, node: &str) {
+        self.points.retain(|_, n| n != node);
+    }
+
+    /// the node owning the first point at or after the key's hash
+    fn node_for(&self, key: &[u8]) -> Option<&str> {
+        let h = hash(key);
+        self.points
+            .range(h..)
+            .next()
+            .or_else(|| self.points.iter().next())
+            .map(|(_, node)| node.as_str())
+    }
+}
+
+/// the first 8 bytes of the key's SHA1, which stay the same across
+/// processes and versions unlike `std`'s hasher
+fn hash(bytes: &[u8]) -> u64 {
+    let digest = sha1_smol::Sha1::from(bytes).digest().bytes();
+    let mut first = [0; 8];
+    first.copy_from_slice(&digest[..8]);
+    u64::from_be_bytes(first)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{HashRing, ShardedClient};
+    use crate::{client::ClientConfig, error::Error, lexi_data::LexiData, stub::Stub};
+
+    fn owners(ring: &HashRing) -> Vec<String> {
+        (0..1000)
+            .map(|i| {
+                ring.node_for(format!("key:{}", i).as_bytes())
+                    .unwrap()
+                    .to_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_ring_spreads_keys_evenly() {
+        let mut ring = HashRing::new(160);
+        for node in ["a", "b", "c"] {
+            ring.add(node);
+        }
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for node in owners(&ring) {
+            *counts.entry(node).or_default() += 1;
+        }
+        assert_eq!(counts.len(), 3);
+        assert!(counts.values().all(|&n| n > 200), "{:?}", counts);
+    }
+
+    #[test]
+    fn the_ring_only_moves_keys_to_an_added_node() {
+        let mut ring = HashRing::new(160);
+        ring.add("a");
+        ring.add("b");
+        let before = owners(&ring);
+        ring.add("c");
+        let after = owners(&ring);
+        let moved = before.iter().zip(&after).filter(|(b, a)| b != a);
+        assert!(moved.clone().all(|(_, a)| a == "c"));
+        assert!(moved.count() > 0);
+
+        ring.remove("c");
+        assert_eq!(owners(&ring), before);
+    }
+
+    fn leak(s: String) -> &'static [u8] {
+        Box::leak(s.into_boxed_str()).as_bytes()
+    }
+
+    #[tokio::test]
+    async fn it_splits_multi_key_commands_per_shard() -> anyhow::Result<()> {
+        let (first, second) = (Stub::bind().await?, Stub::bind().await?);
+        let mut sharded = ShardedClient::new(&[first.addr(), second.addr()])?;
+        let key_on = |addr: &str| {
+            (0..)
+                .map(|i| format!("k{}", i))
+                .find(|key| sharded.node_for(key.as_str()).unwrap() == addr)
+                .unwrap()
+        };
+        let (a, b) = (key_on(first.addr()), key_on(second.addr()));
+        let request = |command: &str, key: &str| {
+            leak(format!(
+                "*2\r\n${}\r\n{}\r\n${}\r\n{}\r\n",
+                command.len(),
+                command,
+                key.len(),
+                key
+            ))
+        };
+        let first = first.play(vec![
+            (request("MGET", &a), b"*1\r\n$1\r\n1\r\n"),
+            (request("MDEL", &a), b":1\r\n"),
+        ]);
+        let second = second.play(vec![
+            (request("MGET", &b), b"*1\r\n+NONE\r\n"),
+            (request("MDEL", &b), b":0\r\n"),
+        ]);
+        sharded.connect().await?;
+        let values: Vec<Option<String>> = sharded.mget(&[b.as_str(), a.as_str()]).await?;
+        assert_eq!(values, vec![None, Some("1".to_owned())]);
+        assert_eq!(sharded.mdel(&[a.as_str(), b.as_str()]).await?, 1);
+        first.finish().await?;
+        second.finish().await
+    }
+
+    #[test]
+    fn keys_hash_the_same_whatever_their_type() -> anyhow::Result<()> {
+        let sharded = ShardedClient::with_configs(vec![
+            ClientConfig::new("127.0.0.1:5173")?,
+            ClientConfig::new("127.0.0.1:5174")?,
+        ]);
+        for i in 0..100 {
+            assert_eq!(
+                sharded.node_for(i)?,
+                sharded.node_for(LexiData::from(i.to_string()))?
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_fails_without_nodes_and_rejects_duplicates() -> anyhow::Result<()> {
+        let mut sharded = ShardedClient::new(&["127.0.0.1:5173", "127.0.0.1:5173"])?;
+        assert_eq!(sharded.nodes().count(), 1);
+        let err = sharded
+            .add_node(ClientConfig::new("127.0.0.1:5173")?)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::DuplicateNode("127.0.0.1:5173".to_owned()))
+        );
+        assert!(sharded.remove_node("127.0.0.1:5173").is_some());
+
+        let err = sharded.get::<i64>("a").await.unwrap_err();
+        assert_eq!(err.downcast_ref::<Error>(), Some(&Error::NoNodes));
+        assert!(sharded.mset(&[("a", 1)]).await.is_err());
+        assert_eq!(sharded.mdel::<&str>(&[]).await?, 0);
+        Ok(())
+    }
+}
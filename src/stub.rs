@@ -0,0 +1,84 @@
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    task::JoinHandle,
+};
+
+/// A fake lexidb server for tests. It accepts a single connection and
+/// expects to receive each request in `script` in order, answering each
+/// with the paired reply. `Bound::play_each` scripts several connections.
+pub struct Stub {
+    addr: String,
+    handle: JoinHandle<anyhow::Result<()>>,
+}
+
+/// A stub that is listening but has no script yet, for scripts that depend
+/// on its address.
+pub struct Bound {
+    addr: String,
+    listener: TcpListener,
+}
+
+impl Stub {
+    pub async fn serve(script: Vec<(&'static [u8], &'static [u8])>) -> anyhow::Result<Self> {
+        Ok(Self::bind().await?.play(script))
+    }
+
+    pub async fn bind() -> anyhow::Result<Bound> {
+        Self::bind_to("127.0.0.1:0").await
+    }
+
+    /// listens on a given address, for servers that come back up
+    pub async fn bind_to(addr: &str) -> anyhow::Result<Bound> {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?.to_string();
+        Ok(Bound { addr, listener })
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// waits for the whole script to have been played
+    pub async fn finish(self) -> anyhow::Result<()> {
+        self.handle.await?
+    }
+}
+
+impl Bound {
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    pub fn play(self, script: Vec<(&'static [u8], &'static [u8])>) -> Stub {
+        self.play_each(vec![script])
+    }
+
+    /// plays each script on its own connection, accepting them in order,
+    /// for clients that reconnect
+    pub fn play_each(self, scripts: Vec<Vec<(&'static [u8], &'static [u8])>>) -> Stub {
+        let Bound { addr, listener } = self;
+        let handle = tokio::spawn(async move {
+            // earlier connections stay open until the last script is done
+            let mut streams = Vec::new();
+            for script in scripts {
+                let (mut stream, _) = listener.accept().await?;
+                for (exp, reply) in script {
+                    let mut buf = vec![0; exp.len()];
+                    stream.read_exact(&mut buf).await?;
+                    if buf != exp {
+                        return Err(anyhow::anyhow!(
+                            "expected {:?}, got {:?}",
+                            String::from_utf8_lossy(exp),
+                            String::from_utf8_lossy(&buf)
+                        ));
+                    }
+                    stream.write_all(reply).await?;
+                }
+                streams.push(stream);
+            }
+            Ok(())
+        });
+        Stub { addr, handle }
+    }
+}
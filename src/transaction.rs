@@ -0,0 +1,256 @@
+use std::{marker::PhantomData, time::Duration};
+
+use bytes::BytesMut;
+
+use crate::{
+    encoder::Encoder,
+    lexi_data::{FromLexiData, LexiData, TryIntoLexiData},
+};
+
+/// Commands queued to run atomically with `Client::exec`.
+///
+/// Every queued command hands back a `Queued` handle, which picks that
+/// command's typed reply out of the `TxResults`:
+///
+/// ```ignore
+/// let mut tx = Transaction::new();
+/// tx.set("a", 1);
+/// let b = tx.incrby("b", 2);
+/// let results = client.exec(tx).await?.expect("watched keys changed");
+/// let b: i64 = results.get(b)?;
+/// ```
+///
+/// Nothing is sent until the transaction is executed, so dropping it
+/// discards the commands. A value that fails to encode, such as a
+/// `codec::Json` that does not serialize, fails the transaction when it is
+/// executed.
+#[derive(Debug, Default)]
+pub struct Transaction {
+    pub(crate) buf: BytesMut,
+    pub(crate) len: usize,
+    /// the first command that could not be queued, failing `Client::exec`
+    pub(crate) error: Option<anyhow::Error>,
+}
+
+/// The position of a command in a `Transaction`, typed by its reply.
+#[derive(Debug)]
+pub struct Queued<T> {
+    index: usize,
+    reply: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Queued<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Queued<T> {}
+
+/// The replies of an executed `Transaction`, one per queued command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TxResults {
+    replies: Vec<LexiData>,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How many commands are queued.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Queues any command, for those without a helper below.
+    pub fn command<T, A>(&mut self, name: &str, args: impl IntoIterator<Item = A>) -> Queued<T>
+    where
+        A: Into<LexiData>,
+    {
+        let args: Vec<LexiData> = args.into_iter().map(Into::into).collect();
+        let mut enc = Encoder::new(&mut self.buf);
+        enc.add_arr(1 + args.len()).add_bulk(name);
+        for arg in &args {
+            enc.add_data(arg);
+        }
+        self.queued()
+    }
+
+    pub fn set(&mut self, key: impl Into<LexiData>, value: impl TryIntoLexiData) -> Queued<()> {
+        match value.try_into_lexi_data() {
+            Ok(value) => self.command("SET", [key.into(), value]),
+            Err(e) => self.fail(e),
+        }
+    }
+
+    pub fn get<T: FromLexiData>(&mut self, key: impl Into<LexiData>) -> Queued<T> {
+        self.command("GET", [key])
+    }
+
+    pub fn del(&mut self, key: impl Into<LexiData>) -> Queued<LexiData> {
+        self.command("DEL", [key])
+    }
+
+    pub fn incr(&mut self, key: impl Into<LexiData>) -> Queued<i64> {
+        self.command("INCR", [key])
+    }
+
+    pub fn decr(&mut self, key: impl Into<LexiData>) -> Queued<i64> {
+        self.command("DECR", [key])
+    }
+
+    pub fn incrby(&mut self, key: impl Into<LexiData>, by: i64) -> Queued<i64> {
+        self.command("INCRBY", [key.into(), by.into()])
+    }
+
+    pub fn decrby(&mut self, key: impl Into<LexiData>, by: i64) -> Queued<i64> {
+        self.command("DECRBY", [key.into(), by.into()])
+    }
+
+    /// Queues a `PEXPIRE`. A `ttl` too long for an i64 of milliseconds
+    /// fails the transaction when it is executed, as `Client::expire` would.
+    pub fn expire(&mut self, key: impl Into<LexiData>, ttl: Duration) -> Queued<bool> {
+        match i64::try_from(ttl.as_millis()) {
+            Ok(ms) => self.command("PEXPIRE", [key.into(), ms.into()]),
+            Err(e) => self.fail(e.into()),
+        }
+    }
+
+    pub fn hset(
+        &mut self,
+        key: impl Into<LexiData>,
+        field: impl Into<LexiData>,
+        value: impl TryIntoLexiData,
+    ) -> Queued<bool> {
+        match value.try_into_lexi_data() {
+            Ok(value) => self.command("HSET", [key.into(), field.into(), value]),
+            Err(e) => self.fail(e),
+        }
+    }
+
+    pub fn hincrby(
+        &mut self,
+        key: impl Into<LexiData>,
+        field: impl Into<LexiData>,
+        by: i64,
+    ) -> Queued<i64> {
+        self.command("HINCRBY", [key.into(), field.into(), by.into()])
+    }
+
+    pub fn sadd(&mut self, key: impl Into<LexiData>, member: impl Into<LexiData>) -> Queued<usize> {
+        self.command("SADD", [key.into(), member.into()])
+    }
+
+    pub fn rpush(
+        &mut self,
+        key: impl Into<LexiData>,
+        value: impl TryIntoLexiData,
+    ) -> Queued<usize> {
+        match value.try_into_lexi_data() {
+            Ok(value) => self.command("RPUSH", [key.into(), value]),
+            Err(e) => self.fail(e),
+        }
+    }
+
+    pub fn zadd(
+        &mut self,
+        key: impl Into<LexiData>,
+        score: f64,
+        member: impl Into<LexiData>,
+    ) -> Queued<bool> {
+        self.command("ZADD", [key.into(), score.into(), member.into()])
+    }
+
+    /// records the first command that could not be queued, for `exec` to
+    /// fail with
+    fn fail<T>(&mut self, e: anyhow::Error) -> Queued<T> {
+        self.error.get_or_insert(e);
+        self.queued()
+    }
+
+    fn queued<T>(&mut self) -> Queued<T> {
+        self.len += 1;
+        Queued {
+            index: self.len - 1,
+            reply: PhantomData,
+        }
+    }
+}
+
+impl TxResults {
+    pub(crate) fn new(replies: Vec<LexiData>) -> Self {
+        Self { replies }
+    }
+
+    /// Decodes the reply to a queued command. A command that failed inside
+    /// the transaction comes back as `Error::Server`.
+    pub fn get<T: FromLexiData>(&self, queued: Queued<T>) -> anyhow::Result<T> {
+        match self.replies.get(queued.index) {
+            Some(data) => T::from_lexi_data(data.clone()),
+            None => Err(anyhow::anyhow!(
+                "no reply for command {} of {}",
+                queued.index,
+                self.replies.len()
+            )),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.replies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.replies.is_empty()
+    }
+
+    pub fn into_vec(self) -> Vec<LexiData> {
+        self.replies
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Transaction, TxResults};
+    use crate::{
+        error::Error,
+        lexi_data::{LexiData, SimpleString},
+    };
+
+    #[test]
+    fn it_encodes_queued_commands() {
+        let mut tx = Transaction::new();
+        tx.set("a", 1);
+        tx.incrby("b", -2);
+        assert_eq!(tx.len(), 2);
+        assert_eq!(
+            &tx.buf[..],
+            b"*3\r\n$3\r\nSET\r\n$1\r\na\r\n:1\r\n*3\r\n$6\r\nINCRBY\r\n$1\r\nb\r\n:-2\r\n"
+        );
+    }
+
+    #[test]
+    fn it_decodes_replies_by_handle() -> anyhow::Result<()> {
+        let mut tx = Transaction::new();
+        let set = tx.set("a", 1);
+        let incr = tx.incr("a");
+        let get = tx.get::<String>("a");
+        let results = TxResults::new(vec![
+            LexiData::Simple(SimpleString::Ok),
+            LexiData::Int(2),
+            LexiData::Error("wrong type".to_owned()),
+        ]);
+        results.get(set)?;
+        assert_eq!(results.get(incr)?, 2);
+        let err = results.get(get).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::Server("wrong type".to_owned()))
+        );
+        Ok(())
+    }
+}